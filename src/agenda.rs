@@ -1,28 +1,410 @@
+use crate::{
+    archive,
+    board::Member,
+    bus::Publisher,
+    config, history, ics,
+    permission::Action,
+    poll::{self, Poll},
+    reminder, rsvp,
+    schedule::{self, Override},
+    todo::{self, Todo},
+    user::{self, User},
+    vote::{self, Ballot},
+};
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use std::{fmt, fs};
-use tokio::sync::mpsc;
+use std::{cmp::Reverse, fmt, fs, str::FromStr, sync::Mutex};
+
+const HELP: &str = "Available commands:\n```\
+!add    -- Add something\n\
+!agenda -- Print the agenda (--full for notes and decisions, --by-votes to sort by votes)\n\
+!edit   -- Change the title of an item (!edit <#ID> <new title>)\n\
+!move   -- Move an item (!move <#ID> <position>)\n\
+!top    -- Move an item to the top (!top <#ID>)\n\
+!prio   -- Set the priority of an item, highest first (!prio <#ID> <priority>)\n\
+!remove -- Remove an item by #ID or title\n\
+!discussed -- Mark an item as discussed (!discussed <#ID>)\n\
+!postpone -- Postpone an item to the next meeting (!postpone <#ID>)\n\
+!note   -- Add a note to an item (!note <#ID> <text>)\n\
+!decision -- Record a decision on an item (!decision <#ID> <text>)\n\
+!clear  -- Remove all items\n\
+!done   -- Close the meeting and archive the agenda (also !close-meeting)\n\
+!confirm -- Go ahead with !clear or !done\n\
+!history -- Print the agenda of a past meeting (!history [YYYY-MM-DD])\n\
+!cancel -- Cancel a meeting (!cancel <YYYY-MM-DD>)\n\
+!reschedule -- Move a meeting (!reschedule <YYYY-MM-DD> <YYYY-MM-DD HH:MM>)\n\
+!extra-meeting -- Add a meeting (!extra-meeting <YYYY-MM-DD HH:MM>)\n\
+!ics    -- Get the next meeting and its agenda as a calendar file\n\
+!export -- Get the agenda as a file (!export md|html)\n\
+!undo   -- Undo the last change to the agenda\n\
+!redo   -- Redo the last undone change\n\
+!todo   -- Add an action item (!todo @user <text> [by <YYYY-MM-DD>])\n\
+!todos  -- Print the open action items\n\
+!todo-done -- Mark an action item as done (!todo-done <#ID>)\n\
+!rsvp   -- Tell if you're coming to the next meeting (!rsvp yes|no|maybe)\n\
+!attending -- Print who is coming to the next meeting\n\
+!board  -- Print what each board member has added to and voted for on the agenda\n\
+!poll   -- Start a poll (!poll \"<question>\" <option> | <option> ... [until <YYYY-MM-DD HH:MM>])\n\
+!poll-close -- Close a poll and post the result (!poll-close <#ID>)\n\
+!help```";
+
+// Seconds to confirm a destructive command in.
+const CONFIRM_TIMEOUT: i64 = 60;
+
+/// A destructive command that has to be confirmed before it is carried out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Confirmable {
+    Clear,
+    CloseMeeting,
+    // Redoing an undone !clear
+    RedoClear,
+}
+
+struct Pending {
+    command: Confirmable,
+    user: User,
+    expires: DateTime<Local>,
+}
+
+// The command waiting for confirmation. Shared by both platforms so that it
+// can be confirmed on either.
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AgendaPoint {
+    // Agendas written before points had IDs are given new ones when read.
+    #[serde(default)]
+    id: u32,
     title: String,
     adder: String,
+    // Not set for points added before users were kept track of.
+    #[serde(default)]
+    adder_user: Option<User>,
+    #[serde(default)]
+    edited: Option<Edit>,
+    #[serde(default)]
+    priority: Option<i32>,
+    #[serde(default)]
+    status: Status,
+    // The date of the meeting this point was carried over from, if any.
+    #[serde(default)]
+    carried_over: Option<NaiveDate>,
+    #[serde(default)]
+    notes: Vec<Note>,
+    #[serde(default)]
+    decisions: Vec<Note>,
+    // Users who have voted for the point on either platform.
+    #[serde(default)]
+    votes: Vec<User>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Edit {
+    by: String,
+    at: DateTime<Local>,
+}
+
+/// A note or decision taken during the meeting.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Note {
+    text: String,
+    author: String,
+    at: DateTime<Local>,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.text, self.author)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteKind {
+    Note,
+    Decision,
+}
+
+impl fmt::Display for NoteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                NoteKind::Note => "Note",
+                NoteKind::Decision => "Decision",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Status {
+    #[default]
+    Open,
+    Discussed,
+    Postponed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Status::Open => "open",
+                Status::Discussed => "discussed",
+                Status::Postponed => "postponed",
+            }
+        )
+    }
 }
 
 impl fmt::Display for AgendaPoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} ({})", self.title, self.adder)
+        write!(f, "#{} {} ({}", self.id, self.title, self.adder)?;
+        if let Some(edit) = &self.edited {
+            write!(f, ", edited by {}", edit.by)?;
+        }
+        write!(f, ")")?;
+        if let Some(priority) = self.priority {
+            write!(f, " [priority {}]", priority)?;
+        }
+        if self.status != Status::Open {
+            write!(f, " [{}]", self.status)?;
+        }
+        if let Some(date) = self.carried_over {
+            write!(f, " (carried over from {})", date)?;
+        }
+        match self.votes.len() {
+            0 => {}
+            1 => write!(f, " [1 vote]")?,
+            votes => write!(f, " [{} votes]", votes)?,
+        }
+        Ok(())
     }
 }
 
 impl AgendaPoint {
+    fn new(id: u32, title: &str, adder: &str) -> Self {
+        Self {
+            id,
+            title: title.to_string(),
+            adder: adder.to_string(),
+            adder_user: None,
+            edited: None,
+            priority: None,
+            status: Status::Open,
+            carried_over: None,
+            notes: Vec::new(),
+            decisions: Vec::new(),
+            votes: Vec::new(),
+        }
+    }
+
+    /// Notes and decisions, as (kind, note).
+    fn annotations(&self) -> impl Iterator<Item = (NoteKind, &Note)> {
+        self.decisions
+            .iter()
+            .map(|note| (NoteKind::Decision, note))
+            .chain(self.notes.iter().map(|note| (NoteKind::Note, note)))
+    }
+
+    pub fn to_note_message(&self, kind: NoteKind, note: &Note) -> String {
+        format!("{} on #{} '{}': {}", kind, self.id, self.title, note)
+    }
+
     pub fn to_add_message(&self) -> String {
-        format!("'{}' added by {}", self.title, self.adder)
+        format!("#{} '{}' added by {}", self.id, self.title, self.adder)
     }
+
+    pub fn to_remove_message(&self) -> String {
+        format!("#{} '{}' removed", self.id, self.title)
+    }
+
+    pub fn to_edit_message(&self, old_title: &str) -> String {
+        format!(
+            "#{} '{}' changed to '{}' by {}",
+            self.id,
+            old_title,
+            self.title,
+            self.edited.as_ref().map_or(&self.adder, |edit| &edit.by)
+        )
+    }
+
+    pub fn to_move_message(&self, position: usize) -> String {
+        format!(
+            "#{} '{}' moved to position {}",
+            self.id, self.title, position
+        )
+    }
+
+    pub fn to_status_message(&self) -> String {
+        format!("#{} '{}' marked as {}", self.id, self.title, self.status)
+    }
+
+    fn added_by(&self, user: &User) -> bool {
+        match &self.adder_user {
+            Some(adder) => (adder.platform, &adder.id) == (user.platform, &user.id),
+            // Points added before users were kept track of only have a name.
+            None => self.adder == user.name,
+        }
+    }
+
+    fn sort_key(&self) -> Reverse<i32> {
+        Reverse(self.priority.unwrap_or(0))
+    }
+}
+
+/// Changes to the agenda that are relayed to the other platforms.
+#[derive(Clone, Debug)]
+pub enum AgendaEvent {
+    Added(AgendaPoint),
+    Removed(AgendaPoint),
+    Edited {
+        old_title: String,
+        point: AgendaPoint,
+    },
+    Moved {
+        point: AgendaPoint,
+        position: usize,
+    },
+    StatusChanged(AgendaPoint),
+    Noted {
+        point: AgendaPoint,
+        kind: NoteKind,
+        note: Note,
+    },
+    // Who cleared it
+    Cleared(String),
+    Closed(NaiveDate),
+    ScheduleChanged(Override),
+    TodoAdded(Todo),
+    TodoDone(Todo),
+    PollOpened(Poll),
+    // The result message of the poll
+    PollClosed(String),
+    Undone {
+        operation: Operation,
+        agenda: String,
+    },
+    Redone {
+        operation: Operation,
+        agenda: String,
+    },
 }
 
-#[derive(Deserialize, Serialize)]
+impl fmt::Display for AgendaEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgendaEvent::Added(point) => write!(f, "{}", point.to_add_message()),
+            AgendaEvent::Removed(point) => write!(f, "{}", point.to_remove_message()),
+            AgendaEvent::Edited { old_title, point } => {
+                write!(f, "{}", point.to_edit_message(old_title))
+            }
+            AgendaEvent::Moved { point, position } => {
+                write!(f, "{}", point.to_move_message(*position))
+            }
+            AgendaEvent::StatusChanged(point) => write!(f, "{}", point.to_status_message()),
+            AgendaEvent::Noted { point, kind, note } => {
+                write!(f, "{}", point.to_note_message(*kind, note))
+            }
+            AgendaEvent::Cleared(name) => write!(f, "Agenda cleared by {}", name),
+            AgendaEvent::Closed(date) => write!(f, "{}", to_close_message(*date)),
+            AgendaEvent::ScheduleChanged(change) => write!(f, "{}", change),
+            AgendaEvent::TodoAdded(todo) => write!(f, "{}", todo.to_add_message()),
+            AgendaEvent::TodoDone(todo) => write!(f, "{}", todo.to_done_message()),
+            AgendaEvent::PollOpened(poll) => write!(f, "{}", poll.to_ballot_message()),
+            AgendaEvent::PollClosed(result) => write!(f, "{}", result),
+            AgendaEvent::Undone { operation, agenda } => {
+                write!(f, "Undid {}. The agenda is now:\n{}", operation, agenda)
+            }
+            AgendaEvent::Redone { operation, agenda } => {
+                write!(f, "Redid {}. The agenda is now:\n{}", operation, agenda)
+            }
+        }
+    }
+}
+
+impl AgendaEvent {
+    /// A file to post together with the message, as (file name, content).
+    pub fn attachment(&self) -> Option<(String, String)> {
+        match self {
+            AgendaEvent::Closed(date) => minutes_file(*date),
+            _ => None,
+        }
+    }
+
+    /// The ballot to post the message as, if it can be voted on.
+    pub fn ballot(&self) -> Option<Ballot> {
+        match self {
+            AgendaEvent::PollOpened(poll) => Some(poll.ballot()),
+            _ => None,
+        }
+    }
+}
+
+/// A change to the agenda that can be undone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Operation {
+    Add(AgendaPoint),
+    Remove {
+        point: AgendaPoint,
+        index: usize,
+    },
+    Clear(Vec<AgendaPoint>),
+    Edit {
+        id: u32,
+        old_title: String,
+        new_title: String,
+    },
+    // The order of the points as (ID, priority), before and after.
+    Reorder {
+        before: Vec<(u32, Option<i32>)>,
+        after: Vec<(u32, Option<i32>)>,
+    },
+}
+
+impl Operation {
+    /// What `user` has to be allowed to do to undo or redo the operation.
+    fn action(&self, undo: bool, user: &User) -> Option<Action> {
+        match (self, undo) {
+            (Operation::Add(point), true) | (Operation::Remove { point, .. }, false)
+                if !point.added_by(user) =>
+            {
+                Some(Action::RemoveOthers)
+            }
+            (Operation::Clear(_), false) => Some(Action::Clear),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add(point) => write!(f, "adding #{} '{}'", point.id, point.title),
+            Operation::Remove { point, .. } => {
+                write!(f, "removing #{} '{}'", point.id, point.title)
+            }
+            Operation::Clear(_) => write!(f, "clearing the agenda"),
+            Operation::Edit {
+                id,
+                old_title,
+                new_title,
+            } => write!(f, "changing #{} '{}' to '{}'", id, old_title, new_title),
+            Operation::Reorder { .. } => write!(f, "reordering the agenda"),
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
 pub struct Agenda {
     points: Vec<AgendaPoint>,
+    // The last ID handed out. Kept when the agenda is cleared so that IDs are
+    // never reused.
+    #[serde(default)]
+    last_id: u32,
 }
 
 impl Agenda {
@@ -33,12 +415,419 @@ impl Agenda {
         )
         .expect("Can't write agenda.json");
     }
+
+    /// Order the points by priority, keeping the current order for points with
+    /// the same priority.
+    fn sort(&mut self) {
+        self.points.sort_by_key(AgendaPoint::sort_key);
+    }
+
+    /// Move the point at index `i` to `position` (counting from 1) and return
+    /// its new index.
+    ///
+    /// The point takes the priority of its new neighbours so that it stays put
+    /// when the agenda is sorted.
+    fn move_to(&mut self, i: usize, position: usize) -> usize {
+        // Sorting may move the point, so it is found again by ID.
+        let id = self.points[i].id;
+        self.sort();
+        let i = self.points.iter().position(|point| point.id == id).unwrap();
+        let mut point = self.points.remove(i);
+        let new = position.saturating_sub(1).min(self.points.len());
+        if let Some(neighbour) = self.points.get(new).or_else(|| self.points.last()) {
+            point.priority = neighbour.priority;
+        }
+        self.points.insert(new, point);
+        new
+    }
+
+    /// Give the point at index `i` a new priority and return its index in the
+    /// sorted agenda.
+    fn set_priority(&mut self, i: usize, priority: i32) -> usize {
+        let id = self.points[i].id;
+        self.points[i].priority = Some(priority);
+        self.sort();
+        self.points.iter().position(|point| point.id == id).unwrap()
+    }
+
+    /// Keep only the points that weren't discussed, carried over from the
+    /// meeting on `date`, and return the agenda of the meeting. Nothing is
+    /// done if the meeting is already `archived`.
+    fn close(&mut self, date: NaiveDate, archived: Option<&Agenda>) -> Option<Agenda> {
+        if archived.is_some() {
+            return None;
+        }
+        let closed = Agenda {
+            points: self.points.clone(),
+            last_id: self.last_id,
+        };
+        self.points.retain(|p| p.status != Status::Discussed);
+        for point in &mut self.points {
+            point.status = Status::Open;
+            point.carried_over = Some(date);
+        }
+        Some(closed)
+    }
+
+    /// Like `to_string` but with notes and decisions.
+    pub fn to_full_string(&self) -> String {
+        if self.points.is_empty() {
+            return self.to_string();
+        }
+        self.sorted_points()
+            .iter()
+            .map(|point| {
+                let mut s = point.to_string();
+                for (kind, note) in point.annotations() {
+                    s.push_str(&format!("\n    {}: {}", kind, note));
+                }
+                s
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn sorted_points(&self) -> Vec<&AgendaPoint> {
+        let mut points = self.points.iter().collect::<Vec<_>>();
+        points.sort_by_key(|p| p.sort_key());
+        points
+    }
+
+    /// What each board member has added to and voted for on the agenda.
+    fn to_board_status(&self, board: &[Member]) -> String {
+        if board.is_empty() {
+            return "No board members configured".to_string();
+        }
+        board
+            .iter()
+            .map(|member| {
+                let added = self
+                    .sorted_points()
+                    .iter()
+                    .filter(|point| {
+                        point
+                            .adder_user
+                            .as_ref()
+                            .is_some_and(|user| member.is(user))
+                    })
+                    .map(|point| format!("#{}", point.id))
+                    .collect::<Vec<_>>();
+                let voted = self
+                    .points
+                    .iter()
+                    .any(|point| point.votes.iter().any(|user| member.is(user)));
+                format!(
+                    "{}: {}, {}",
+                    member.name,
+                    match added.as_slice() {
+                        [] => "nothing added".to_string(),
+                        added => format!("added {}", added.join(" ")),
+                    },
+                    if voted { "voted" } else { "not voted" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// What `command` would do to the agenda. For a redone clear the agenda
+    /// only has the points that would be removed again.
+    fn to_confirm_message(&self, command: Confirmable) -> String {
+        match command {
+            Confirmable::Clear => format!(
+                "This will remove all {} items from the agenda:\n{}",
+                self.points.len(),
+                self
+            ),
+            Confirmable::RedoClear => format!(
+                "This will remove {} items from the agenda again:\n{}",
+                self.points.len(),
+                self
+            ),
+            Confirmable::CloseMeeting => {
+                let discussed = self
+                    .points
+                    .iter()
+                    .filter(|point| point.status == Status::Discussed)
+                    .count();
+                format!(
+                    "This will close the meeting and archive the agenda. {} discussed items \
+                     will be removed and {} carried over to the next meeting.",
+                    discussed,
+                    self.points.len() - discussed
+                )
+            }
+        }
+    }
+
+    fn order(&self) -> Vec<(u32, Option<i32>)> {
+        self.points
+            .iter()
+            .map(|point| (point.id, point.priority))
+            .collect()
+    }
+
+    fn set_order(&mut self, order: &[(u32, Option<i32>)]) {
+        for (id, priority) in order {
+            if let Some(point) = self.points.iter_mut().find(|point| point.id == *id) {
+                point.priority = *priority;
+            }
+        }
+        // Points added since are kept last.
+        self.points.sort_by_key(|point| {
+            order
+                .iter()
+                .position(|(id, _)| *id == point.id)
+                .unwrap_or(usize::MAX)
+        });
+    }
+
+    fn insert(&mut self, index: usize, point: &AgendaPoint) {
+        if !self.points.iter().any(|p| p.id == point.id) {
+            self.points
+                .insert(index.min(self.points.len()), point.clone());
+        }
+    }
+
+    /// Apply `operation` again, or revert it if `undo` is set.
+    fn apply(&mut self, operation: &Operation, undo: bool) {
+        match (operation, undo) {
+            (Operation::Add(point), false) => self.insert(self.points.len(), point),
+            (Operation::Remove { point, index }, true) => self.insert(*index, point),
+            (Operation::Add(point), true) | (Operation::Remove { point, .. }, false) => {
+                self.points.retain(|p| p.id != point.id)
+            }
+            (Operation::Clear(points), false) => self
+                .points
+                .retain(|p| !points.iter().any(|point| point.id == p.id)),
+            (Operation::Clear(points), true) => {
+                for (i, point) in points.iter().enumerate() {
+                    self.insert(i, point);
+                }
+            }
+            (
+                Operation::Edit {
+                    id,
+                    old_title,
+                    new_title,
+                },
+                undo,
+            ) => {
+                if let Some(point) = self.points.iter_mut().find(|point| point.id == *id) {
+                    point.title = if undo { old_title } else { new_title }.clone();
+                }
+            }
+            (Operation::Reorder { before, after }, undo) => {
+                self.set_order(if undo { before } else { after })
+            }
+        }
+    }
+
+    fn points_by_votes(&self) -> Vec<&AgendaPoint> {
+        let mut points = self.sorted_points();
+        points.sort_by_key(|p| Reverse(p.votes.len()));
+        points
+    }
+
+    /// The points as a numbered list that can be voted on with reactions,
+    /// and the ballot for it. There are only reactions for the first
+    /// `vote::MAX_OPTIONS` points.
+    fn to_ballot(points: &[&AgendaPoint]) -> (String, Ballot) {
+        let mut s = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| format!("{}. {}", i + 1, point))
+            .collect::<Vec<_>>()
+            .join("\n");
+        s.push_str("\nReact with the numbers of the items you want to discuss.");
+        if points.len() > vote::MAX_OPTIONS {
+            s.push_str(&format!(
+                " Only items 1-{} can be voted on.",
+                vote::MAX_OPTIONS
+            ));
+        }
+        let points = &points[..points.len().min(vote::MAX_OPTIONS)];
+        (
+            s,
+            Ballot::Agenda(points.iter().map(|point| point.id).collect()),
+        )
+    }
+
+    /// Add or remove `user`'s vote for the point with ID `id`.
+    pub fn vote(&mut self, id: u32, user: User, add: bool) {
+        if let Some(point) = self.points.iter_mut().find(|point| point.id == id) {
+            point
+                .votes
+                .retain(|voter| (voter.platform, &voter.id) != (user.platform, &user.id));
+            if add {
+                point.votes.push(user);
+            }
+            self.write();
+        }
+    }
+
+    pub fn export(&self, format: ExportFormat, meeting: Option<DateTime<Local>>) -> String {
+        match format {
+            ExportFormat::Markdown => self.to_markdown(meeting),
+            ExportFormat::Html => self.to_html(meeting),
+        }
+    }
+
+    fn heading(meeting: Option<DateTime<Local>>) -> String {
+        match meeting {
+            Some(meeting) => format!(
+                "Agenda for the meeting on {}",
+                meeting.format("%Y-%m-%d %H:%M")
+            ),
+            None => "Agenda".to_string(),
+        }
+    }
+
+    pub fn to_markdown(&self, meeting: Option<DateTime<Local>>) -> String {
+        let mut s = format!("# {}\n\n", Agenda::heading(meeting));
+        if self.points.is_empty() {
+            s.push_str("Empty agenda\n");
+        }
+        for (i, point) in self.sorted_points().iter().enumerate() {
+            s.push_str(&format!(
+                "{}. **{}** (#{}, added by {}) - {}\n",
+                i + 1,
+                point.title,
+                point.id,
+                point.adder,
+                point.status
+            ));
+            if let Some(date) = point.carried_over {
+                s.push_str(&format!("   *Carried over from {}*\n", date));
+            }
+            for (kind, note) in point.annotations() {
+                s.push_str(&format!("   - {}: {}\n", kind, note));
+            }
+        }
+        s
+    }
+
+    pub fn to_html(&self, meeting: Option<DateTime<Local>>) -> String {
+        let heading = escape_html(&Agenda::heading(meeting));
+        let mut s = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+            heading, heading
+        );
+        if self.points.is_empty() {
+            s.push_str("<p>Empty agenda</p>\n");
+        } else {
+            s.push_str("<ol>\n");
+            for point in self.sorted_points() {
+                s.push_str(&format!(
+                    "<li><strong>{}</strong> (#{}, added by {}) - {}",
+                    escape_html(&point.title),
+                    point.id,
+                    escape_html(&point.adder),
+                    point.status
+                ));
+                if let Some(date) = point.carried_over {
+                    s.push_str(&format!("<br><em>Carried over from {}</em>", date));
+                }
+                if point.annotations().next().is_some() {
+                    s.push_str("\n<ul>\n");
+                    for (kind, note) in point.annotations() {
+                        s.push_str(&format!(
+                            "<li>{}: {}</li>\n",
+                            kind,
+                            escape_html(&note.to_string())
+                        ));
+                    }
+                    s.push_str("</ul>\n");
+                }
+                s.push_str("</li>\n");
+            }
+            s.push_str("</ol>\n");
+        }
+        s.push_str("</body>\n</html>\n");
+        s
+    }
+
+    /// A draft of the minutes of the meeting on `date` with one section per
+    /// point.
+    pub fn to_minutes(&self, date: NaiveDate) -> String {
+        let mut s = format!("# Minutes of the board meeting {}\n\n", date);
+        let mut section = 0;
+        let mut heading = |title: &str| {
+            section += 1;
+            format!("## §{} {}\n\n", section, title)
+        };
+        s.push_str(&heading("Opening"));
+        s.push_str("The meeting was opened at __:__ by ____.\n\n");
+        s.push_str(&heading("Attendance"));
+        s.push_str("Present: \n\n");
+        s.push_str(&heading("Election of secretary and adjuster"));
+        s.push_str("Secretary: \n\nAdjuster: \n\n");
+        for point in self.sorted_points() {
+            s.push_str(&heading(&point.title));
+            s.push_str(&format!("Proposed by {}.\n\n", point.adder));
+            for (kind, note) in point.annotations() {
+                s.push_str(&format!("{}: {}\n\n", kind, note.text));
+            }
+        }
+        s.push_str(&heading("Closing"));
+        s.push_str("The meeting was closed at __:__.\n");
+        s
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Find the index of the point referred to by `query`, either by its ID
+    /// (with or without a leading '#') or by (part of) its title.
+    fn find(&self, query: &str) -> Result<usize, String> {
+        if let Ok(id) = query.trim_start_matches('#').parse::<u32>() {
+            return self
+                .points
+                .iter()
+                .position(|p| p.id == id)
+                .ok_or_else(|| format!("There is no item #{}", id));
+        }
+
+        let lowercase = query.trim().to_lowercase();
+        if lowercase.is_empty() {
+            return Err("No item given, expected a title or an #ID".to_string());
+        }
+        if let Some(i) = self
+            .points
+            .iter()
+            .position(|p| p.title.to_lowercase() == lowercase)
+        {
+            return Ok(i);
+        }
+        let matches = self
+            .points
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.title.to_lowercase().contains(&lowercase))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        match matches.as_slice() {
+            [i] => Ok(*i),
+            [] => Err(format!("No item matches '{}'", query)),
+            _ => Err(format!(
+                "'{}' matches more than one item:\n{}",
+                query,
+                matches
+                    .iter()
+                    .map(|i| self.points[*i].to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        }
+    }
 }
 
 impl fmt::Display for Agenda {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = self
-            .points
+            .sorted_points()
             .iter()
             .map(|p| p.to_string())
             .collect::<Vec<_>>()
@@ -54,50 +843,1003 @@ impl fmt::Display for Agenda {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            _ => Err(format!("Unknown format '{}', expected md or html", s)),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 pub enum Emoji {
     Ok,
     Confused,
     Err,
 }
 
-pub fn parse_message<F>(
+pub fn parse_message<F, G, H, I>(
     message: &str,
-    sender: &str,
+    sender: &User,
+    resolve_user: H,
     send_message: F,
-    point_sender: &mpsc::UnboundedSender<AgendaPoint>,
+    send_file: G,
+    send_ballot: I,
+    event_sender: &Publisher,
 ) -> Option<Emoji>
 where
     F: FnOnce(String),
+    G: FnOnce(&str, String),
+    H: FnOnce(&str) -> Option<User>,
+    I: FnOnce(String, Ballot),
 {
-    if message.starts_with("!add ") {
+    // Not a command, so the config isn't needed.
+    if !message.starts_with('!') {
+        return Some(Emoji::Err);
+    }
+    let config = config::read_config();
+    if let Some(command) = message.split_whitespace().next() {
+        if config.board_only.iter().any(|c| c == command) && config.member(sender).is_none() {
+            send_message(format!("Only board members can use {}", command));
+            return Some(Emoji::Confused);
+        }
+    }
+
+    if let Some(title) = message.strip_prefix("!add ") {
         let mut agenda = read_agenda();
         let agenda_point = AgendaPoint {
-            title: message[5..].to_string(),
-            adder: sender.to_string(),
+            adder_user: Some(sender.clone()),
+            ..AgendaPoint::new(agenda.next_id(), title, &sender.name)
         };
-        point_sender.send(agenda_point.clone()).unwrap();
-        agenda.points.push(agenda_point);
+        event_sender.send(AgendaEvent::Added(agenda_point.clone()));
+        agenda.points.push(agenda_point.clone());
         agenda.write();
+        record(Operation::Add(agenda_point));
         Some(Emoji::Ok)
-    } else if message.starts_with("!agenda") {
-        send_message(read_agenda().to_string());
+    } else if let Some(query) = message.strip_prefix("!remove ") {
+        let mut agenda = read_agenda();
+        match agenda.find(query.trim()) {
+            Ok(i) => {
+                if !agenda.points[i].added_by(sender) {
+                    if let Err(e) = config.permissions.check(sender, Action::RemoveOthers) {
+                        send_message(e);
+                        return Some(Emoji::Confused);
+                    }
+                }
+                let agenda_point = agenda.points.remove(i);
+                agenda.write();
+                record(Operation::Remove {
+                    point: agenda_point.clone(),
+                    index: i,
+                });
+                send_message(agenda_point.to_remove_message());
+                event_sender.send(AgendaEvent::Removed(agenda_point));
+                Some(Emoji::Ok)
+            }
+            Err(e) => {
+                send_message(e);
+                Some(Emoji::Confused)
+            }
+        }
+    } else if let Some(args) = message.strip_prefix("!edit ") {
+        let mut agenda = read_agenda();
+        let (query, title) = match args.trim().split_once(' ') {
+            Some((query, title)) if !title.trim().is_empty() => (query, title.trim()),
+            _ => {
+                send_message("Usage: !edit <#ID> <new title>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        match agenda.find(query) {
+            Ok(i) => {
+                let agenda_point = &mut agenda.points[i];
+                let old_title = std::mem::replace(&mut agenda_point.title, title.to_string());
+                agenda_point.edited = Some(Edit {
+                    by: sender.name.clone(),
+                    at: Local::now(),
+                });
+                let agenda_point = agenda_point.clone();
+                agenda.write();
+                record(Operation::Edit {
+                    id: agenda_point.id,
+                    old_title: old_title.clone(),
+                    new_title: agenda_point.title.clone(),
+                });
+                event_sender.send(AgendaEvent::Edited {
+                    old_title,
+                    point: agenda_point,
+                });
+                Some(Emoji::Ok)
+            }
+            Err(e) => {
+                send_message(e);
+                Some(Emoji::Confused)
+            }
+        }
+    } else if let Some(args) = message.strip_prefix("!move ") {
+        let (query, position) = match split_args::<usize>(args) {
+            Some(args) => args,
+            None => {
+                send_message("Usage: !move <#ID> <position>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        reorder(
+            query,
+            |agenda, i| agenda.move_to(i, position),
+            send_message,
+            event_sender,
+        )
+    } else if let Some(query) = message.strip_prefix("!top ") {
+        reorder(
+            query,
+            |agenda, i| agenda.move_to(i, 1),
+            send_message,
+            event_sender,
+        )
+    } else if let Some(args) = message.strip_prefix("!prio ") {
+        let (query, priority) = match split_args::<i32>(args) {
+            Some(args) => args,
+            None => {
+                send_message("Usage: !prio <#ID> <priority>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        reorder(
+            query,
+            |agenda, i| agenda.set_priority(i, priority),
+            send_message,
+            event_sender,
+        )
+    } else if let Some(query) = message.strip_prefix("!discussed ") {
+        set_status(query, Status::Discussed, send_message, event_sender)
+    } else if let Some(query) = message.strip_prefix("!postpone ") {
+        set_status(query, Status::Postponed, send_message, event_sender)
+    } else if let Some(args) = message.strip_prefix("!note ") {
+        add_note(
+            args,
+            NoteKind::Note,
+            &sender.name,
+            send_message,
+            event_sender,
+        )
+    } else if let Some(args) = message.strip_prefix("!decision ") {
+        add_note(
+            args,
+            NoteKind::Decision,
+            &sender.name,
+            send_message,
+            event_sender,
+        )
+    } else if let Some(args) = message.strip_prefix("!agenda") {
+        let agenda = read_agenda();
+        if agenda.points.is_empty() {
+            send_message(agenda.to_string());
+        } else if args.trim() == "--full" {
+            send_message(agenda.to_full_string());
+        } else if args.trim() == "--by-votes" {
+            let (message, ballot) = Agenda::to_ballot(&agenda.points_by_votes());
+            send_ballot(message, ballot);
+        } else {
+            let (message, ballot) = Agenda::to_ballot(&agenda.sorted_points());
+            send_ballot(message, ballot);
+        }
         None
     } else if message.starts_with("!clear") {
-        Agenda { points: Vec::new() }.write();
+        if let Err(e) = config.permissions.check(sender, Action::Clear) {
+            send_message(e);
+            return Some(Emoji::Confused);
+        }
+        let agenda = read_agenda();
+        if agenda.points.is_empty() {
+            send_message("The agenda is already empty".to_string());
+            return None;
+        }
+        send_message(ask_confirmation(Confirmable::Clear, sender, &agenda));
+        None
+    } else if message.starts_with("!done") || message.starts_with("!close-meeting") {
+        if let Err(e) = config.permissions.check(sender, Action::CloseMeeting) {
+            send_message(e);
+            return Some(Emoji::Confused);
+        }
+        send_message(ask_confirmation(
+            Confirmable::CloseMeeting,
+            sender,
+            &read_agenda(),
+        ));
+        None
+    } else if message.starts_with("!confirm") {
+        confirm(sender, send_message, send_file, event_sender)
+    } else if let Some(date) = message.strip_prefix("!history") {
+        let date = date.trim();
+        let dates = archive::dates();
+        let date = if date.is_empty() {
+            match dates.last() {
+                Some(date) => *date,
+                None => {
+                    send_message("No meetings have been archived".to_string());
+                    return None;
+                }
+            }
+        } else {
+            match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(date) => date,
+                Err(_) => {
+                    send_message("Usage: !history [YYYY-MM-DD]".to_string());
+                    return Some(Emoji::Confused);
+                }
+            }
+        };
+        send_message(match archive::read(date) {
+            Some(agenda) => format!(
+                "Agenda for the meeting on {}:\n{}\nArchived meetings: {}",
+                date,
+                agenda.to_full_string(),
+                dates
+                    .iter()
+                    .map(|date| date.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => format!("No agenda archived for {}", date),
+        });
+        None
+    } else if let Some(date) = message.strip_prefix("!cancel ") {
+        if let Err(e) = config.permissions.check(sender, Action::ChangeSchedule) {
+            send_message(e);
+            return Some(Emoji::Confused);
+        }
+        let date = match schedule::parse_date(date) {
+            Some(date) => date,
+            None => {
+                send_message("Usage: !cancel <YYYY-MM-DD>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        let mut overrides = schedule::read_overrides();
+        if !config.schedule.has_meeting_on(&overrides, date) {
+            send_message(format!("There is no meeting on {}", date));
+            return Some(Emoji::Confused);
+        }
+        overrides.cancel(date);
+        event_sender.send(AgendaEvent::ScheduleChanged(Override::Cancel(date)));
+        Some(Emoji::Ok)
+    } else if let Some(args) = message.strip_prefix("!reschedule ") {
+        if let Err(e) = config.permissions.check(sender, Action::ChangeSchedule) {
+            send_message(e);
+            return Some(Emoji::Confused);
+        }
+        let (from, to) = match args.trim().split_once(' ').and_then(|(from, to)| {
+            Some((schedule::parse_date(from)?, schedule::parse_datetime(to)?))
+        }) {
+            Some(args) => args,
+            None => {
+                send_message("Usage: !reschedule <YYYY-MM-DD> <YYYY-MM-DD HH:MM>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        let mut overrides = schedule::read_overrides();
+        if !config.schedule.has_meeting_on(&overrides, from) {
+            send_message(format!("There is no meeting on {}", from));
+            return Some(Emoji::Confused);
+        }
+        overrides.reschedule(from, to);
+        event_sender.send(AgendaEvent::ScheduleChanged(Override::Move { from, to }));
+        Some(Emoji::Ok)
+    } else if let Some(at) = message.strip_prefix("!extra-meeting ") {
+        if let Err(e) = config.permissions.check(sender, Action::ChangeSchedule) {
+            send_message(e);
+            return Some(Emoji::Confused);
+        }
+        let at = match schedule::parse_datetime(at) {
+            Some(at) => at,
+            None => {
+                send_message("Usage: !extra-meeting <YYYY-MM-DD HH:MM>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        schedule::read_overrides().add_extra(at);
+        event_sender.send(AgendaEvent::ScheduleChanged(Override::Extra(at)));
         Some(Emoji::Ok)
+    } else if message.starts_with("!ics") {
+        match reminder::next_meeting() {
+            Some(meeting) => send_file(
+                "meeting.ics",
+                ics::render_meeting(meeting.with_timezone(&Utc), &read_agenda()),
+            ),
+            None => send_message("There are no upcoming meetings".to_string()),
+        }
+        None
+    } else if let Some(format) = message.strip_prefix("!export") {
+        match format.trim().parse::<ExportFormat>() {
+            Ok(format) => {
+                send_file(
+                    &format!("agenda.{}", format.extension()),
+                    read_agenda().export(format, reminder::next_meeting()),
+                );
+                None
+            }
+            Err(e) => {
+                send_message(e);
+                Some(Emoji::Confused)
+            }
+        }
+    } else if message.starts_with("!undo") || message.starts_with("!redo") {
+        let undo = message.starts_with("!undo");
+        let mut history = history::read_history();
+        let operation = if undo { history.undo() } else { history.redo() };
+        match operation {
+            Some(operation) => {
+                if let Some(action) = operation.action(undo, sender) {
+                    if let Err(e) = config.permissions.check(sender, action) {
+                        send_message(e);
+                        return Some(Emoji::Confused);
+                    }
+                }
+                if let (Operation::Clear(points), false) = (&operation, undo) {
+                    // Only the points that were cleared are removed, not
+                    // those added since.
+                    let cleared = Agenda {
+                        points: read_agenda()
+                            .points
+                            .into_iter()
+                            .filter(|p| points.iter().any(|point| point.id == p.id))
+                            .collect(),
+                        ..Agenda::default()
+                    };
+                    // Taken from the history again once confirmed.
+                    send_message(ask_confirmation(Confirmable::RedoClear, sender, &cleared));
+                    return None;
+                }
+                apply_from_history(history, operation, undo, send_message, event_sender);
+                Some(Emoji::Ok)
+            }
+            None => {
+                send_message(format!("Nothing to {}", if undo { "undo" } else { "redo" }));
+                Some(Emoji::Confused)
+            }
+        }
+    } else if let Some(args) = message.strip_prefix("!todo ") {
+        let args = args.trim();
+        let (assignee, assignee_user, rest) = match user::parse_mention(args) {
+            Some((id, rest)) => match resolve_user(id) {
+                Some(user) => (user.name.clone(), Some(user), rest),
+                None => (id.to_string(), None, rest),
+            },
+            None => match args.strip_prefix('@').and_then(|args| args.split_once(' ')) {
+                Some((name, rest)) => (name.to_string(), None, rest),
+                None => ("".to_string(), None, ""),
+            },
+        };
+        let (text, due) = todo::parse_due(rest);
+        if assignee.is_empty() || text.is_empty() {
+            send_message("Usage: !todo @user <text> [by <YYYY-MM-DD>]".to_string());
+            return Some(Emoji::Confused);
+        }
+        let todo = todo::read_todos().add(text, &assignee, assignee_user, &sender.name, due);
+        event_sender.send(AgendaEvent::TodoAdded(todo));
+        Some(Emoji::Ok)
+    } else if message.starts_with("!todos") {
+        send_message(todo::read_todos().to_string());
+        None
+    } else if let Some(id) = message.strip_prefix("!todo-done") {
+        let id = match id.trim().trim_start_matches('#').parse::<u32>() {
+            Ok(id) => id,
+            Err(_) => {
+                send_message("Usage: !todo-done <#ID>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        match todo::read_todos().mark_done(id) {
+            Some(todo) => {
+                event_sender.send(AgendaEvent::TodoDone(todo));
+                Some(Emoji::Ok)
+            }
+            None => {
+                send_message(format!("No open action item #{}", id));
+                Some(Emoji::Confused)
+            }
+        }
+    } else if let Some(answer) = message.strip_prefix("!rsvp") {
+        let answer = match answer.parse::<rsvp::Answer>() {
+            Ok(answer) => answer,
+            Err(e) => {
+                send_message(e);
+                return Some(Emoji::Confused);
+            }
+        };
+        match reminder::next_meeting() {
+            Some(meeting) => {
+                rsvp::read_rsvps(meeting).respond(sender.clone(), answer, &config);
+                Some(Emoji::Ok)
+            }
+            None => {
+                send_message("There are no upcoming meetings".to_string());
+                Some(Emoji::Confused)
+            }
+        }
+    } else if message.starts_with("!board") {
+        send_message(read_agenda().to_board_status(&config.board));
+        None
+    } else if message.starts_with("!attending") {
+        match reminder::next_meeting() {
+            Some(meeting) => match rsvp::read_rsvps(meeting).summary(&config) {
+                summary if summary.is_empty() => {
+                    send_message("Nobody has answered yet, use !rsvp yes|no|maybe".to_string())
+                }
+                summary => send_message(summary),
+            },
+            None => send_message("There are no upcoming meetings".to_string()),
+        }
+        None
+    } else if let Some(args) = message.strip_prefix("!poll ") {
+        let (question, options, closes) = match poll::parse_poll(args) {
+            Some(poll) if poll.1.len() <= vote::MAX_OPTIONS => poll,
+            _ => {
+                send_message(format!(
+                    "Usage: !poll \"<question>\" <option> | <option> ... [until <YYYY-MM-DD HH:MM>] (at most {} options)",
+                    vote::MAX_OPTIONS
+                ));
+                return Some(Emoji::Confused);
+            }
+        };
+        let closes = closes.map(|closes| {
+            schedule::resolve_local(config.schedule.timezone(), closes).with_timezone(&Utc)
+        });
+        let poll = poll::read_polls().add(question, options, &sender.name, closes);
+        send_ballot(poll.to_ballot_message(), poll.ballot());
+        event_sender.send(AgendaEvent::PollOpened(poll));
+        None
+    } else if let Some(id) = message.strip_prefix("!poll-close") {
+        let id = match id.trim().trim_start_matches('#').parse::<u32>() {
+            Ok(id) => id,
+            Err(_) => {
+                send_message("Usage: !poll-close <#ID>".to_string());
+                return Some(Emoji::Confused);
+            }
+        };
+        match poll::read_polls().close(id) {
+            Some(poll) => {
+                let result = poll.to_result_message(&config);
+                send_message(result.clone());
+                event_sender.send(AgendaEvent::PollClosed(result));
+                None
+            }
+            None => {
+                send_message(format!("No open poll #{}", id));
+                Some(Emoji::Confused)
+            }
+        }
     } else if message.starts_with("!help") {
-        send_message("Available commands:\n```!add    -- Add something\n!agenda -- Print the agenda\n!clear  -- Remove all items\n!help```".to_string());
+        send_message(HELP.to_string());
         None
-    } else if message.starts_with("!") {
-        Some(Emoji::Confused)
     } else {
-        Some(Emoji::Err)
+        Some(Emoji::Confused)
+    }
+}
+
+/// Move the point matching `query` with `move_point`, which is given its
+/// index and returns its new one.
+fn reorder<F, M>(
+    query: &str,
+    move_point: M,
+    send_message: F,
+    event_sender: &Publisher,
+) -> Option<Emoji>
+where
+    F: FnOnce(String),
+    M: FnOnce(&mut Agenda, usize) -> usize,
+{
+    let mut agenda = read_agenda();
+    match agenda.find(query.trim()) {
+        Ok(i) => {
+            let before = agenda.order();
+            let new = move_point(&mut agenda, i);
+            agenda.write();
+            record(Operation::Reorder {
+                before,
+                after: agenda.order(),
+            });
+            event_sender.send(AgendaEvent::Moved {
+                point: agenda.points[new].clone(),
+                position: new + 1,
+            });
+            Some(Emoji::Ok)
+        }
+        Err(e) => {
+            send_message(e);
+            Some(Emoji::Confused)
+        }
+    }
+}
+
+fn set_status<F>(
+    query: &str,
+    status: Status,
+    send_message: F,
+    event_sender: &Publisher,
+) -> Option<Emoji>
+where
+    F: FnOnce(String),
+{
+    let mut agenda = read_agenda();
+    match agenda.find(query.trim()) {
+        Ok(i) => {
+            agenda.points[i].status = status;
+            agenda.write();
+            event_sender.send(AgendaEvent::StatusChanged(agenda.points[i].clone()));
+            Some(Emoji::Ok)
+        }
+        Err(e) => {
+            send_message(e);
+            Some(Emoji::Confused)
+        }
     }
 }
 
+fn add_note<F>(
+    args: &str,
+    kind: NoteKind,
+    sender: &str,
+    send_message: F,
+    event_sender: &Publisher,
+) -> Option<Emoji>
+where
+    F: FnOnce(String),
+{
+    let mut agenda = read_agenda();
+    let (query, text) = match split_args::<String>(args) {
+        Some((query, text)) if !text.is_empty() => (query, text),
+        _ => {
+            send_message(format!(
+                "Usage: !{} <#ID> <text>",
+                kind.to_string().to_lowercase()
+            ));
+            return Some(Emoji::Confused);
+        }
+    };
+    match agenda.find(query) {
+        Ok(i) => {
+            let note = Note {
+                text,
+                author: sender.to_string(),
+                at: Local::now(),
+            };
+            let point = &mut agenda.points[i];
+            match kind {
+                NoteKind::Note => point.notes.push(note.clone()),
+                NoteKind::Decision => point.decisions.push(note.clone()),
+            }
+            let point = point.clone();
+            agenda.write();
+            event_sender.send(AgendaEvent::Noted { point, kind, note });
+            Some(Emoji::Ok)
+        }
+        Err(e) => {
+            send_message(e);
+            Some(Emoji::Confused)
+        }
+    }
+}
+
+/// Wait for `user` to confirm `command` and tell what it would do.
+fn ask_confirmation(command: Confirmable, user: &User, agenda: &Agenda) -> String {
+    *PENDING.lock().unwrap() = Some(Pending {
+        command,
+        user: user.clone(),
+        expires: Local::now() + chrono::Duration::seconds(CONFIRM_TIMEOUT),
+    });
+    format!(
+        "{}\nReply !confirm or react with a check mark within {} seconds to go ahead.",
+        agenda.to_confirm_message(command),
+        CONFIRM_TIMEOUT
+    )
+}
+
+pub fn awaiting_confirmation() -> bool {
+    PENDING.lock().unwrap().is_some()
+}
+
+/// Carry out the command waiting for confirmation, if `sender` is the one who
+/// asked for it and it hasn't timed out.
+pub fn confirm<F, G>(
+    sender: &User,
+    send_message: F,
+    send_file: G,
+    event_sender: &Publisher,
+) -> Option<Emoji>
+where
+    F: FnOnce(String),
+    G: FnOnce(&str, String),
+{
+    let mut pending = PENDING.lock().unwrap();
+    let command = match pending.take() {
+        Some(p) if p.expires < Local::now() => {
+            send_message("Too late, the command has timed out".to_string());
+            return Some(Emoji::Confused);
+        }
+        Some(p) if !config::read_config().same_person(&p.user, sender) => {
+            send_message(format!("Only {} can confirm", p.user.name));
+            *pending = Some(p);
+            return Some(Emoji::Confused);
+        }
+        Some(p) => p.command,
+        None => {
+            send_message("Nothing to confirm".to_string());
+            return Some(Emoji::Confused);
+        }
+    };
+    drop(pending);
+    match command {
+        Confirmable::Clear => {
+            let mut agenda = read_agenda();
+            let points = std::mem::take(&mut agenda.points);
+            agenda.write();
+            record(Operation::Clear(points));
+            event_sender.send(AgendaEvent::Cleared(sender.name.clone()));
+        }
+        Confirmable::CloseMeeting => {
+            // The same date as auto close uses, so that a meeting is only
+            // closed once.
+            let date = reminder::previous_meeting()
+                .map(|meeting| meeting.date())
+                .unwrap_or_else(Local::today)
+                .naive_local();
+            if !close_meeting(date) {
+                send_message(format!("The meeting on {} is already closed", date));
+                return Some(Emoji::Confused);
+            }
+            if let Some((filename, minutes)) = minutes_file(date) {
+                send_file(&filename, minutes);
+            }
+            event_sender.send(AgendaEvent::Closed(date));
+        }
+        Confirmable::RedoClear => {
+            let mut history = history::read_history();
+            match history.redo() {
+                Some(operation @ Operation::Clear(_)) => {
+                    apply_from_history(history, operation, false, send_message, event_sender)
+                }
+                _ => {
+                    send_message("Nothing to redo".to_string());
+                    return Some(Emoji::Confused);
+                }
+            }
+        }
+    }
+    Some(Emoji::Ok)
+}
+
+/// Apply an operation just taken from `history` and tell everyone about it.
+fn apply_from_history<F>(
+    history: history::History,
+    operation: Operation,
+    undo: bool,
+    send_message: F,
+    event_sender: &Publisher,
+) where
+    F: FnOnce(String),
+{
+    let mut agenda = read_agenda();
+    agenda.apply(&operation, undo);
+    agenda.write();
+    history.write();
+    let agenda = agenda.to_string();
+    let event = if undo {
+        AgendaEvent::Undone { operation, agenda }
+    } else {
+        AgendaEvent::Redone { operation, agenda }
+    };
+    send_message(event.to_string());
+    event_sender.send(event);
+}
+
+/// Remember a change to the agenda so it can be undone.
+fn record(operation: Operation) {
+    let mut history = history::read_history();
+    history.record(operation);
+    history.write();
+}
+
+/// Split "<item> <value>" command arguments.
+fn split_args<T: FromStr>(args: &str) -> Option<(&str, T)> {
+    let (query, value) = args.trim().split_once(' ')?;
+    Some((query, value.trim().parse().ok()?))
+}
+
+pub fn to_close_message(date: NaiveDate) -> String {
+    format!(
+        "Meeting closed. The agenda has been archived, see !history {}",
+        date
+    )
+}
+
+/// Archive the current agenda as the agenda of the meeting on `date` and start
+/// a new agenda with the points that weren't discussed. Returns false if the
+/// meeting was already closed, e.g. by hand before it was closed
+/// automatically.
+pub fn close_meeting(date: NaiveDate) -> bool {
+    let mut agenda = read_agenda();
+    let archived = match agenda.close(date, archive::read(date).as_ref()) {
+        Some(archived) => archived,
+        None => return false,
+    };
+    archive::write(date, &archived);
+    archive::write_minutes(date, &archived.to_minutes(date));
+    agenda.write();
+    // Changes made before the meeting can't be undone on the new agenda.
+    history::History::default().write();
+    true
+}
+
+/// The minutes draft of the meeting on `date` as (file name, content).
+pub fn minutes_file(date: NaiveDate) -> Option<(String, String)> {
+    archive::read_minutes(date).map(|minutes| (format!("minutes-{}.md", date), minutes))
+}
+
 pub fn read_agenda() -> Agenda {
     match fs::read_to_string("agenda.json") {
-        Ok(s) => serde_json::from_str(&s).expect("Error parsing agenda.json"),
-        Err(_) => Agenda { points: Vec::new() },
+        Ok(s) => {
+            let mut agenda: Agenda = serde_json::from_str(&s).expect("Error parsing agenda.json");
+            for i in 0..agenda.points.len() {
+                if agenda.points[i].id == 0 {
+                    agenda.points[i].id = agenda.next_id();
+                }
+            }
+            agenda
+        }
+        Err(_) => Agenda::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::Platform;
+
+    fn agenda(titles: &[&str]) -> Agenda {
+        let mut agenda = Agenda::default();
+        for title in titles {
+            let point = AgendaPoint::new(agenda.next_id(), title, "someone");
+            agenda.points.push(point);
+        }
+        agenda
+    }
+
+    #[test]
+    fn find() {
+        let mut agenda = agenda(&["Budget", "Budget 2021", "Pub crawl"]);
+        agenda.points.remove(0);
+        assert_eq!(agenda.find("2"), Ok(0));
+        assert_eq!(agenda.find("#3"), Ok(1));
+        assert!(agenda.find("1").is_err());
+        assert!(agenda.find("#4").is_err());
+        assert_eq!(agenda.find("budget 2021"), Ok(0));
+        assert_eq!(agenda.find("crawl"), Ok(1));
+        assert!(agenda.find("u").is_err());
+        assert!(agenda.find("meeting").is_err());
+        agenda.points.remove(0);
+        assert!(agenda.find("").is_err());
+        assert!(agenda.find("  ").is_err());
+    }
+
+    #[test]
+    fn move_to() {
+        let ids = |agenda: &Agenda| agenda.points.iter().map(|p| p.id).collect::<Vec<_>>();
+        let mut agenda = agenda(&["a", "b", "c", "d"]);
+        agenda.points[1].priority = Some(1);
+        agenda.sort();
+        assert_eq!(ids(&agenda), vec![2, 1, 3, 4]);
+
+        assert_eq!(agenda.move_to(3, 2), 1);
+        assert_eq!(ids(&agenda), vec![2, 4, 1, 3]);
+        assert_eq!(agenda.move_to(0, 10), 3);
+        assert_eq!(ids(&agenda), vec![4, 1, 3, 2]);
+        assert_eq!(agenda.move_to(2, 1), 0);
+        assert_eq!(ids(&agenda), vec![3, 4, 1, 2]);
+
+        // Points are appended without sorting, so the stored order can be
+        // out of date.
+        let mut unsorted = self::agenda(&["a", "b", "c"]);
+        unsorted.points[1].priority = Some(-1);
+        assert_eq!(unsorted.move_to(2, 1), 0);
+        assert_eq!(ids(&unsorted), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn close() {
+        let date = NaiveDate::from_ymd(2020, 11, 19);
+        let mut agenda = agenda(&["Budget", "Pub crawl", "Board game night"]);
+        agenda.points[0].status = Status::Discussed;
+        agenda.points[1].status = Status::Postponed;
+
+        let archived = agenda.close(date, None).unwrap();
+        assert_eq!(archived.points.len(), 3);
+        assert_eq!(archived.last_id, 3);
+        assert_eq!(
+            agenda
+                .points
+                .iter()
+                .map(|p| (p.id, p.status, p.carried_over))
+                .collect::<Vec<_>>(),
+            vec![(2, Status::Open, Some(date)), (3, Status::Open, Some(date))]
+        );
+
+        // Closing the same meeting again, e.g. automatically after closing it
+        // by hand, neither archives nor carries over anything.
+        let point = AgendaPoint::new(agenda.next_id(), "Added after the meeting", "someone");
+        agenda.points.push(point);
+        assert!(agenda.close(date, Some(&archived)).is_none());
+        assert_eq!(agenda.points[2].carried_over, None);
+    }
+
+    #[test]
+    fn export() {
+        let mut agenda = agenda(&["Budget", "<script>"]);
+        agenda.points[0].status = Status::Discussed;
+        let markdown = agenda.to_markdown(None);
+        assert!(markdown.starts_with("# Agenda\n"));
+        assert!(markdown.contains("1. **Budget** (#1, added by someone) - discussed\n"));
+        assert!(markdown.contains("2. **<script>** (#2, added by someone) - open\n"));
+        let html = agenda.to_html(None);
+        assert!(html.contains("<li><strong>&lt;script&gt;</strong>"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn to_minutes() {
+        let minutes = agenda(&["Budget"]).to_minutes(NaiveDate::from_ymd(2020, 11, 19));
+        assert!(minutes.starts_with("# Minutes of the board meeting 2020-11-19\n"));
+        assert!(minutes.contains("## §3 Election of secretary and adjuster\n"));
+        assert!(minutes.contains("## §4 Budget\n\nProposed by someone.\n"));
+        assert!(minutes.contains("## §5 Closing\n"));
+    }
+
+    #[test]
+    fn to_ballot() {
+        let mut agenda = agenda(&["Budget", "Pub crawl"]);
+        agenda.points[1]
+            .votes
+            .push(User::new(Platform::Slack, "U123", "Gustav"));
+        let (message, ballot) = Agenda::to_ballot(&agenda.points_by_votes());
+        assert_eq!(
+            message,
+            "1. #2 Pub crawl (someone) [1 vote]\n2. #1 Budget (someone)\n\
+             React with the numbers of the items you want to discuss."
+        );
+        assert!(matches!(ballot, Ballot::Agenda(points) if points == vec![2, 1]));
+
+        let titles = (1..=12).map(|i| i.to_string()).collect::<Vec<_>>();
+        let long = self::agenda(&titles.iter().map(String::as_str).collect::<Vec<_>>());
+        let (message, ballot) = Agenda::to_ballot(&long.sorted_points());
+        assert!(message.contains("\n12. #12 12 (someone)\n"));
+        assert!(message.ends_with("Only items 1-10 can be voted on."));
+        assert_eq!(ballot.options(), 10);
+    }
+
+    #[test]
+    fn to_board_status() {
+        let board: Vec<Member> = serde_json::from_str(
+            r#"[{ "name": "Alice", "slack": "U1" }, { "name": "Bob", "discord": "2" }]"#,
+        )
+        .unwrap();
+        let alice = User::new(Platform::Slack, "U1", "Alice");
+        let mut agenda = agenda(&["Budget", "Pub crawl"]);
+        agenda.points[1].adder_user = Some(alice.clone());
+        agenda.points[0].votes.push(alice);
+        assert_eq!(
+            agenda.to_board_status(&board),
+            "Alice: added #2, voted\nBob: nothing added, not voted"
+        );
+    }
+
+    #[test]
+    fn undo() {
+        let mut agenda = agenda(&["Budget", "Pub crawl", "Sauna"]);
+        let titles = |agenda: &Agenda| {
+            agenda
+                .sorted_points()
+                .iter()
+                .map(|point| point.title.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let before = agenda.order();
+        agenda.move_to(2, 1);
+        let reorder = Operation::Reorder {
+            before,
+            after: agenda.order(),
+        };
+        let remove = Operation::Remove {
+            point: agenda.points.remove(1),
+            index: 1,
+        };
+        let clear = Operation::Clear(std::mem::take(&mut agenda.points));
+
+        agenda.apply(&clear, true);
+        assert_eq!(titles(&agenda), vec!["Sauna", "Pub crawl"]);
+        agenda.apply(&remove, true);
+        assert_eq!(titles(&agenda), vec!["Sauna", "Budget", "Pub crawl"]);
+        agenda.apply(&reorder, true);
+        assert_eq!(titles(&agenda), vec!["Budget", "Pub crawl", "Sauna"]);
+        agenda.apply(&reorder, false);
+        assert_eq!(titles(&agenda), vec!["Sauna", "Budget", "Pub crawl"]);
+        agenda.apply(&clear, false);
+        assert_eq!(titles(&agenda), vec!["Budget"]);
+    }
+
+    #[test]
+    fn undo_action() {
+        let alice = User::new(Platform::Slack, "U1", "Alice");
+        let bob = User::new(Platform::Discord, "2", "Bob");
+        let add = Operation::Add(AgendaPoint {
+            adder_user: Some(alice.clone()),
+            ..AgendaPoint::new(1, "Budget", "Alice")
+        });
+        assert_eq!(add.action(true, &alice), None);
+        assert_eq!(add.action(true, &bob), Some(Action::RemoveOthers));
+        assert_eq!(add.action(false, &bob), None);
+        let clear = Operation::Clear(Vec::new());
+        assert_eq!(clear.action(true, &bob), None);
+        assert_eq!(clear.action(false, &bob), Some(Action::Clear));
+    }
+
+    #[test]
+    fn to_confirm_message() {
+        let mut agenda = agenda(&["Budget", "Pub crawl"]);
+        assert_eq!(
+            agenda.to_confirm_message(Confirmable::Clear),
+            "This will remove all 2 items from the agenda:\n\
+             #1 Budget (someone)\n#2 Pub crawl (someone)"
+        );
+        assert_eq!(
+            self::agenda(&["Budget"]).to_confirm_message(Confirmable::RedoClear),
+            "This will remove 1 items from the agenda again:\n#1 Budget (someone)"
+        );
+        agenda.points[0].status = Status::Discussed;
+        assert_eq!(
+            agenda.to_confirm_message(Confirmable::CloseMeeting),
+            "This will close the meeting and archive the agenda. 1 discussed items will be \
+             removed and 1 carried over to the next meeting."
+        );
+    }
+
+    #[test]
+    fn to_full_string() {
+        let mut agenda = agenda(&["Budget", "Pub crawl"]);
+        agenda.points[0].decisions.push(Note {
+            text: "Approved".to_string(),
+            author: "secretary".to_string(),
+            at: Local::now(),
+        });
+        assert_eq!(
+            agenda.to_full_string(),
+            "#1 Budget (someone)\n    Decision: Approved (secretary)\n#2 Pub crawl (someone)"
+        );
     }
 }
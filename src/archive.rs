@@ -0,0 +1,51 @@
+use crate::agenda::Agenda;
+use chrono::NaiveDate;
+use std::{fs, path::PathBuf};
+
+const ARCHIVE_DIR: &str = "archive";
+
+fn path(date: NaiveDate, extension: &str) -> PathBuf {
+    PathBuf::from(ARCHIVE_DIR).join(format!("{}.{}", date, extension))
+}
+
+pub fn write(date: NaiveDate, agenda: &Agenda) {
+    fs::create_dir_all(ARCHIVE_DIR).expect("Can't create archive directory");
+    fs::write(
+        path(date, "json"),
+        serde_json::to_string_pretty(agenda).expect("Can't serialize agenda"),
+    )
+    .expect("Can't write archived agenda");
+}
+
+pub fn read(date: NaiveDate) -> Option<Agenda> {
+    fs::read_to_string(path(date, "json"))
+        .ok()
+        .map(|s| serde_json::from_str(&s).expect("Error parsing archived agenda"))
+}
+
+pub fn write_minutes(date: NaiveDate, minutes: &str) {
+    fs::create_dir_all(ARCHIVE_DIR).expect("Can't create archive directory");
+    fs::write(path(date, "md"), minutes).expect("Can't write minutes");
+}
+
+pub fn read_minutes(date: NaiveDate) -> Option<String> {
+    fs::read_to_string(path(date, "md")).ok()
+}
+
+/// The dates of all archived meetings, oldest first.
+pub fn dates() -> Vec<NaiveDate> {
+    let mut dates = match fs::read_dir(ARCHIVE_DIR) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.parse().ok()
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    dates.sort();
+    dates
+}
@@ -0,0 +1,67 @@
+use crate::user::{Platform, User};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A board member and their user IDs, keyed by platform.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Member {
+    pub name: String,
+    #[serde(flatten)]
+    ids: HashMap<Platform, String>,
+}
+
+impl Member {
+    pub fn is(&self, user: &User) -> bool {
+        self.ids.get(&user.platform) == Some(&user.id)
+    }
+}
+
+/// How many board members need to attend a meeting, either a number or
+/// "Majority".
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Quorum {
+    Members(usize),
+    Rule(QuorumRule),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum QuorumRule {
+    Majority,
+}
+
+impl Quorum {
+    pub fn required(&self, board_size: usize) -> usize {
+        match self {
+            Quorum::Members(members) => *members,
+            Quorum::Rule(QuorumRule::Majority) => board_size / 2 + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quorum() {
+        let quorum: Quorum = serde_json::from_str("4").unwrap();
+        assert_eq!(quorum.required(9), 4);
+        let quorum: Quorum = serde_json::from_str(r#""Majority""#).unwrap();
+        assert_eq!(quorum.required(9), 5);
+        assert_eq!(quorum.required(8), 5);
+    }
+
+    #[test]
+    fn is() {
+        let member: Member =
+            serde_json::from_str(r#"{ "name": "Alice", "slack": "U123", "discord": "1" }"#)
+                .unwrap();
+        let user = |platform, id: &str| User::new(platform, id, "Alice");
+        assert!(member.is(&user(Platform::Slack, "U123")));
+        assert!(!member.is(&user(Platform::Discord, "U123")));
+        assert!(!member.is(&user(Platform::Slack, "U456")));
+        assert!(member.is(&user(Platform::Discord, "1")));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::agenda::AgendaEvent;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Subscribers that fall further behind than this miss the oldest events.
+const CAPACITY: usize = 100;
+
+#[derive(Clone, Debug)]
+struct Published {
+    // The ID of the connection that published the event.
+    origin: usize,
+    event: AgendaEvent,
+}
+
+/// The agenda events from all connections, e.g. chats or a web page. Every
+/// connection publishes what happens on it and subscribes to what happens on
+/// the others.
+#[derive(Clone)]
+pub struct Bus {
+    sender: broadcast::Sender<Published>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            next_id: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl Bus {
+    /// Connect with an ID of its own. The subscriber gets the events published
+    /// after this call by every other connection. `name` is only used in logs.
+    pub fn connect(&self, name: &str) -> (Publisher, Subscriber) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (
+            Publisher {
+                id,
+                sender: self.sender.clone(),
+            },
+            Subscriber {
+                id,
+                name: name.to_string(),
+                receiver: self.sender.subscribe(),
+            },
+        )
+    }
+}
+
+/// Publishes events from one connection.
+#[derive(Clone)]
+pub struct Publisher {
+    id: usize,
+    sender: broadcast::Sender<Published>,
+}
+
+impl Publisher {
+    pub fn send(&self, event: AgendaEvent) {
+        // Fails only if nobody is subscribed, in which case nobody needs it.
+        let _ = self.sender.send(Published {
+            origin: self.id,
+            event,
+        });
+    }
+}
+
+pub struct Subscriber {
+    id: usize,
+    name: String,
+    receiver: broadcast::Receiver<Published>,
+}
+
+impl Subscriber {
+    /// The next event from another connection, or None once every publisher
+    /// is gone.
+    pub async fn recv(&mut self) -> Option<AgendaEvent> {
+        loop {
+            match self.receiver.recv().await {
+                // Our own echo
+                Ok(published) if published.origin == self.id => {}
+                Ok(published) => return Some(published.event),
+                Err(RecvError::Lagged(missed)) => {
+                    println!("{} missed {} agenda events", self.name, missed)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[tokio::test]
+    async fn ignores_echoes() {
+        let bus = Bus::default();
+        // Two connections to the same platform still hear each other.
+        let (first_publisher, mut first) = bus.connect("Discord");
+        let (second_publisher, mut second) = bus.connect("Discord");
+        let date = NaiveDate::from_ymd(2020, 11, 26);
+        first_publisher.send(AgendaEvent::Closed(date));
+        second_publisher.send(AgendaEvent::Cleared("Alice".to_string()));
+        drop((bus, first_publisher, second_publisher));
+
+        assert!(matches!(
+            first.recv().await,
+            Some(AgendaEvent::Cleared(name)) if name == "Alice"
+        ));
+        assert!(first.recv().await.is_none());
+        assert!(matches!(second.recv().await, Some(AgendaEvent::Closed(d)) if d == date));
+        assert!(second.recv().await.is_none());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::agenda::{self, parse_message, AgendaEvent, Emoji};
+use crate::bus::{Bus, Publisher, Subscriber};
+use crate::reminder::ReminderType;
+use crate::user::{Platform, User};
+use crate::vote::{self, Ballot};
+
+use futures::{
+    future::{join_all, Future},
+    FutureExt,
+};
+use std::sync::{mpsc, Arc};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::{spawn, spawn_blocking},
+};
+
+/// A reaction the bot posts or listens for. Every platform names them
+/// differently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reaction {
+    // A command was handled.
+    Ok,
+    // Go ahead with a destructive command.
+    Confirmation,
+    // A vote for an option of a ballot, counting from 0.
+    Option(usize),
+}
+
+/// Something that happened in the agenda channel of a platform.
+pub enum Incoming {
+    Message {
+        id: String,
+        sender: User,
+        text: String,
+        // The users mentioned in the message, if the platform tells.
+        mentions: Vec<User>,
+    },
+    Reaction {
+        message: String,
+        user: User,
+        reaction: String,
+        add: bool,
+    },
+}
+
+/// A chat platform the bot posts to. Messages, files and reactions all go to
+/// the platform's agenda channel. The methods block, so they should be called
+/// outside of the async runtime.
+pub trait ChatAdapter: Send + Sync {
+    fn platform(&self) -> Platform;
+
+    /// The agenda channel.
+    fn channel(&self) -> &str;
+
+    fn send_message(&self, text: &str);
+
+    fn send_file(&self, filename: &str, content: &str);
+
+    /// Post a message with one reaction for every option of `ballot` to vote
+    /// with.
+    fn post_ballot(&self, text: &str, ballot: Ballot);
+
+    fn send_direct_message(&self, user: &User, text: &str);
+
+    /// The name of `reaction` on the platform, as it's posted and received in
+    /// `Incoming::Reaction`. There must be a name for every option up to
+    /// `vote::MAX_OPTIONS`.
+    fn reaction_name(&self, reaction: Reaction) -> &'static str;
+
+    /// React to a message with a reaction named as on the platform.
+    fn react(&self, message: &str, reaction: &str);
+
+    /// Look up a user that wasn't in the mentions of a message.
+    fn resolve_user(&self, id: &str) -> Option<User>;
+
+    /// The meeting reminder as it should be posted, e.g. with a mention.
+    fn to_reminder_message(&self, message: &str) -> String {
+        message.to_string()
+    }
+}
+
+/// A connected platform and the events from its agenda channel.
+pub struct Connection {
+    pub adapter: Arc<dyn ChatAdapter>,
+    pub incoming: mpsc::Receiver<Incoming>,
+}
+
+/// Handle the incoming events of every connection. The agenda events of
+/// every connection are published on `bus` and posted to all others.
+///
+/// Every connection subscribes to `reminders` before this returns, so that
+/// no reminder sent after the call is missed.
+pub fn handle(
+    connections: Vec<Connection>,
+    bus: Bus,
+    reminders: &broadcast::Sender<ReminderType>,
+) -> impl Future<Output = ()> {
+    let mut tasks = Vec::new();
+    for Connection { adapter, incoming } in connections {
+        let (publisher, subscriber) = bus.connect(&format!("{:?}", adapter.platform()));
+        tasks.push(spawn(relay(subscriber, Arc::clone(&adapter))));
+        tasks.push(spawn(handle_reminders(
+            reminders.subscribe(),
+            Arc::clone(&adapter),
+        )));
+        tasks.push(spawn_blocking(move || {
+            receive_events(&*adapter, incoming, publisher)
+        }));
+    }
+    join_all(tasks).map(|_| ())
+}
+
+fn receive_events(
+    adapter: &dyn ChatAdapter,
+    incoming: mpsc::Receiver<Incoming>,
+    publisher: Publisher,
+) {
+    for event in incoming {
+        match event {
+            Incoming::Message {
+                id,
+                sender: user,
+                text,
+                mentions,
+            } => {
+                if let Some(Emoji::Ok) = parse_message(
+                    &text,
+                    &user,
+                    |id: &str| {
+                        mentions
+                            .iter()
+                            .find(|user| user.id == id)
+                            .cloned()
+                            .or_else(|| adapter.resolve_user(id))
+                    },
+                    |s: String| adapter.send_message(&s),
+                    |filename: &str, content: String| adapter.send_file(filename, &content),
+                    |s: String, ballot: Ballot| adapter.post_ballot(&s, ballot),
+                    &publisher,
+                ) {
+                    adapter.react(&id, adapter.reaction_name(Reaction::Ok));
+                }
+            }
+            Incoming::Reaction {
+                message,
+                user,
+                reaction,
+                add,
+            } => match parse_reaction(adapter, &reaction) {
+                Some(Reaction::Confirmation) if add && agenda::awaiting_confirmation() => {
+                    if let Some(Emoji::Ok) = agenda::confirm(
+                        &user,
+                        |s: String| adapter.send_message(&s),
+                        |filename: &str, content: String| adapter.send_file(filename, &content),
+                        &publisher,
+                    ) {
+                        adapter.react(&message, adapter.reaction_name(Reaction::Ok));
+                    }
+                }
+                Some(Reaction::Option(option)) => {
+                    vote::react(adapter.channel(), &message, option, user, add)
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// What a reaction named `name` on the platform of `adapter` is for, if it's
+/// one the bot listens for.
+fn parse_reaction(adapter: &dyn ChatAdapter, name: &str) -> Option<Reaction> {
+    std::iter::once(Reaction::Confirmation)
+        .chain((0..vote::MAX_OPTIONS).map(Reaction::Option))
+        .find(|reaction| adapter.reaction_name(*reaction) == name)
+}
+
+/// Post the agenda events from the other connections.
+async fn relay(mut subscriber: Subscriber, adapter: Arc<dyn ChatAdapter>) {
+    while let Some(event) = subscriber.recv().await {
+        let adapter = Arc::clone(&adapter);
+        spawn_blocking(move || send_event(&*adapter, &event))
+            .await
+            .unwrap();
+    }
+}
+
+fn send_event(adapter: &dyn ChatAdapter, event: &AgendaEvent) {
+    println!("{:?} received '{}'", adapter.platform(), event);
+    match event.ballot() {
+        Some(ballot) => adapter.post_ballot(&event.to_string(), ballot),
+        None => adapter.send_message(&event.to_string()),
+    }
+    if let Some((filename, content)) = event.attachment() {
+        adapter.send_file(&filename, &content);
+    }
+}
+
+async fn handle_reminders(
+    mut reminders: broadcast::Receiver<ReminderType>,
+    adapter: Arc<dyn ChatAdapter>,
+) {
+    loop {
+        let reminder = match reminders.recv().await {
+            Ok(reminder) => reminder,
+            Err(RecvError::Lagged(missed)) => {
+                println!("{:?} missed {} reminders", adapter.platform(), missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let adapter = Arc::clone(&adapter);
+        spawn_blocking(move || send_reminder(&*adapter, &reminder))
+            .await
+            .unwrap();
+    }
+}
+
+fn send_reminder(adapter: &dyn ChatAdapter, reminder: &ReminderType) {
+    match reminder {
+        ReminderType::Meeting { message, target } if target.includes(adapter.platform()) => {
+            adapter.send_message(&adapter.to_reminder_message(message));
+        }
+        ReminderType::MeetingClosed(date) => {
+            adapter.send_message(&agenda::to_close_message(*date));
+            if let Some((filename, content)) = agenda::minutes_file(*date) {
+                adapter.send_file(&filename, &content);
+            }
+        }
+        ReminderType::ActionItems {
+            message,
+            direct_messages,
+        } => {
+            adapter.send_message(message);
+            for (user, message) in direct_messages {
+                if user.platform == adapter.platform() {
+                    adapter.send_direct_message(user, message);
+                }
+            }
+        }
+        ReminderType::PollClosed(message) => adapter.send_message(message),
+        ReminderType::Meeting { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reminder::Target;
+    use std::sync::Mutex;
+
+    /// Records what would have been posted.
+    struct Recorder {
+        platform: Platform,
+        posted: Mutex<Vec<String>>,
+    }
+
+    impl ChatAdapter for Recorder {
+        fn platform(&self) -> Platform {
+            self.platform
+        }
+
+        fn channel(&self) -> &str {
+            "agenda"
+        }
+
+        fn send_message(&self, text: &str) {
+            self.posted.lock().unwrap().push(text.to_string());
+        }
+
+        fn send_file(&self, filename: &str, _content: &str) {
+            self.posted
+                .lock()
+                .unwrap()
+                .push(format!("file {}", filename));
+        }
+
+        fn post_ballot(&self, text: &str, _ballot: Ballot) {
+            self.send_message(text);
+        }
+
+        fn send_direct_message(&self, user: &User, text: &str) {
+            self.posted
+                .lock()
+                .unwrap()
+                .push(format!("@{}: {}", user.name, text));
+        }
+
+        fn reaction_name(&self, reaction: Reaction) -> &'static str {
+            match reaction {
+                Reaction::Ok => "ok",
+                Reaction::Confirmation => "yes",
+                Reaction::Option(option) => {
+                    ["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"][option]
+                }
+            }
+        }
+
+        fn react(&self, _message: &str, _reaction: &str) {}
+
+        fn resolve_user(&self, _id: &str) -> Option<User> {
+            None
+        }
+    }
+
+    fn recorder() -> Recorder {
+        Recorder {
+            platform: Platform::Slack,
+            posted: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn parse_reaction() {
+        let recorder = recorder();
+        assert_eq!(
+            super::parse_reaction(&recorder, "yes"),
+            Some(Reaction::Confirmation)
+        );
+        assert_eq!(
+            super::parse_reaction(&recorder, "10"),
+            Some(Reaction::Option(9))
+        );
+        assert_eq!(super::parse_reaction(&recorder, "ok"), None);
+    }
+
+    #[tokio::test]
+    async fn handle_reminders() {
+        let (sender, receiver) = broadcast::channel(crate::reminder::CAPACITY);
+        // Sent in the same tick, before the first one is posted.
+        for (message, target) in &[
+            ("Meeting on Slack", Target::Only(Platform::Slack)),
+            ("Meeting on Discord", Target::Only(Platform::Discord)),
+            ("Meeting everywhere", Target::Both),
+        ] {
+            sender
+                .send(ReminderType::Meeting {
+                    message: message.to_string(),
+                    target: *target,
+                })
+                .unwrap();
+        }
+        // Polls closing in the same second
+        for message in &["Poll closed", "Another poll closed"] {
+            sender
+                .send(ReminderType::PollClosed(message.to_string()))
+                .unwrap();
+        }
+        drop(sender);
+        let recorder = Arc::new(recorder());
+        super::handle_reminders(receiver, Arc::clone(&recorder) as Arc<dyn ChatAdapter>).await;
+        assert_eq!(
+            *recorder.posted.lock().unwrap(),
+            vec![
+                "Meeting on Slack",
+                "Meeting everywhere",
+                "Poll closed",
+                "Another poll closed"
+            ]
+        );
+    }
+
+    #[test]
+    fn send_reminder() {
+        let recorder = recorder();
+        super::send_reminder(
+            &recorder,
+            &ReminderType::Meeting {
+                message: "Meeting on Discord".to_string(),
+                target: Target::Only(Platform::Discord),
+            },
+        );
+        super::send_reminder(
+            &recorder,
+            &ReminderType::ActionItems {
+                message: "Action items".to_string(),
+                direct_messages: vec![
+                    (
+                        User::new(Platform::Slack, "U1", "Alice"),
+                        "Book the room".to_string(),
+                    ),
+                    (
+                        User::new(Platform::Discord, "2", "Bob"),
+                        "Buy coffee".to_string(),
+                    ),
+                ],
+            },
+        );
+        assert_eq!(
+            *recorder.posted.lock().unwrap(),
+            vec!["Action items", "@Alice: Book the room"]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use crate::board::{Member, Quorum};
+use crate::permission::Permissions;
+use crate::schedule::Schedule;
+use crate::user::User;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Config {
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub board: Vec<Member>,
+    // The number of people that need to attend a meeting. Counts board
+    // members only if there is a board.
+    #[serde(default)]
+    quorum: Option<Quorum>,
+    // Commands only board members can use, e.g. "!clear".
+    #[serde(default)]
+    pub board_only: Vec<String>,
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+impl Config {
+    pub fn quorum(&self) -> Option<usize> {
+        self.quorum.map(|quorum| quorum.required(self.board.len()))
+    }
+
+    pub fn member(&self, user: &User) -> Option<&Member> {
+        self.board.iter().find(|member| member.is(user))
+    }
+
+    /// Whether two users are the same person, either the same user or the
+    /// same board member. Display names aren't compared since anyone can
+    /// take someone else's.
+    pub fn same_person(&self, a: &User, b: &User) -> bool {
+        (a.platform, &a.id) == (b.platform, &b.id)
+            || match (self.member(a), self.member(b)) {
+                (Some(a), Some(b)) => std::ptr::eq(a, b),
+                _ => false,
+            }
+    }
+}
+
+pub fn read_config() -> Config {
+    match fs::read_to_string("config.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing config.json"),
+        Err(_) => Config::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::Platform;
+
+    #[test]
+    fn same_person() {
+        let config: Config = serde_json::from_str(
+            r#"{ "board": [{ "name": "Alice", "slack": "U1", "discord": "1" }] }"#,
+        )
+        .unwrap();
+        let alice = User::new(Platform::Slack, "U1", "Alice");
+        assert!(config.same_person(&alice, &User::new(Platform::Slack, "U1", "Al")));
+        assert!(config.same_person(&alice, &User::new(Platform::Discord, "1", "Ali")));
+        // Someone else who took the same name
+        assert!(!config.same_person(&alice, &User::new(Platform::Discord, "9", "Alice")));
+    }
+}
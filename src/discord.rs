@@ -1,65 +1,190 @@
-use crate::agenda::{self, parse_message, AgendaPoint, Emoji};
-use crate::reminder::ReminderType;
+use crate::chat::{self, ChatAdapter, Connection, Incoming};
+use crate::user::{Platform, User};
+use crate::vote::{self, Ballot};
 
 use discord::{
-    model::{ChannelId, Event, PossibleServer, ReactionEmoji, UserId},
+    model::{ChannelId, Event, MessageId, PossibleServer, Reaction, ReactionEmoji, RoleId, UserId},
     Discord, Error,
 };
-use futures::join;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
-};
-use tokio::{
-    sync::{mpsc, watch},
-    task::{spawn, spawn_blocking},
+    sync::{mpsc, Arc, Mutex},
 };
+use tokio::task::spawn_blocking;
 
 const TOKEN: Option<&str> = None;
 const CHANNEL: Option<ChannelId> = None;
 
+/// The emojis used to vote for the options of a ballot.
+const OPTIONS: [&str; vote::MAX_OPTIONS] = [
+    "1\u{fe0f}\u{20e3}",
+    "2\u{fe0f}\u{20e3}",
+    "3\u{fe0f}\u{20e3}",
+    "4\u{fe0f}\u{20e3}",
+    "5\u{fe0f}\u{20e3}",
+    "6\u{fe0f}\u{20e3}",
+    "7\u{fe0f}\u{20e3}",
+    "8\u{fe0f}\u{20e3}",
+    "9\u{fe0f}\u{20e3}",
+    "\u{1f51f}",
+];
+
+struct Adapter {
+    client: Arc<Mutex<Discord>>,
+    channel: ChannelId,
+    channel_name: String,
+    display_names: Arc<Mutex<HashMap<UserId, String>>>,
+}
+
 struct Handler {
-    _our_id: UserId,
+    our_id: UserId,
     connection: discord::Connection,
-    sender: mpsc::UnboundedSender<AgendaPoint>,
-    client: Arc<Mutex<discord::Discord>>,
+    incoming: mpsc::Sender<Incoming>,
     channel: Option<ChannelId>,
-    display_names: HashMap<UserId, String>,
+    display_names: Arc<Mutex<HashMap<UserId, String>>>,
+    roles: HashMap<UserId, Vec<RoleId>>,
+    role_names: HashMap<RoleId, String>,
 }
 
-pub async fn handle(
-    sender: mpsc::UnboundedSender<AgendaPoint>,
-    receiver: mpsc::UnboundedReceiver<AgendaPoint>,
-    reminder: watch::Receiver<ReminderType>,
-) {
+/// Connect to Discord. Events are received even if no channel is configured,
+/// so that the channels can be listed, but nothing is posted.
+pub async fn connect() -> Option<Connection> {
     println!("Setting up Discord");
 
     let token = std::env::var("DISCORD_API_TOKEN")
         .unwrap_or_else(|_| TOKEN.expect("Missing Discord token").to_string());
-    let client = Discord::from_bot_token(&token);
-
-    if let Ok(client) = client {
-        let (connection, _) = client.connect().expect("Discord connect failed"); //TODO
-        let _our_id = client.get_current_user().unwrap().id;
-        let client = Arc::new(Mutex::new(client));
-
-        let channel = std::env::var("DISCORD_CHANNEL")
-            .map(|id| Some(ChannelId(id.parse::<u64>().unwrap())))
-            .unwrap_or(CHANNEL);
-
-        let (_, _, _) = join!(
-            spawn(receive_from_slack(receiver, Arc::clone(&client), channel)),
-            spawn(handle_reminders(reminder, Arc::clone(&client), channel)),
-            spawn_blocking(move || receive_events(&mut Handler {
-                _our_id,
-                connection,
-                sender,
-                client,
-                channel,
-                display_names: HashMap::new(),
-            })),
+    let client = Discord::from_bot_token(&token).ok()?;
+
+    let (connection, _) = client.connect().expect("Discord connect failed"); //TODO
+    let our_id = client.get_current_user().unwrap().id;
+    let client = Arc::new(Mutex::new(client));
+
+    let channel = std::env::var("DISCORD_CHANNEL")
+        .map(|id| Some(ChannelId(id.parse::<u64>().unwrap())))
+        .unwrap_or(CHANNEL);
+    let display_names = Arc::new(Mutex::new(HashMap::new()));
+    let (sender, incoming) = mpsc::channel();
+
+    let mut handler = Handler {
+        our_id,
+        connection,
+        incoming: sender,
+        channel,
+        display_names: Arc::clone(&display_names),
+        roles: HashMap::new(),
+        role_names: HashMap::new(),
+    };
+    spawn_blocking(move || receive_events(&mut handler));
+
+    let channel = channel?;
+    Some(Connection {
+        adapter: Arc::new(Adapter {
+            client,
+            channel,
+            channel_name: channel.0.to_string(),
+            display_names,
+        }),
+        incoming,
+    })
+}
+
+impl ChatAdapter for Adapter {
+    fn platform(&self) -> Platform {
+        Platform::Discord
+    }
+
+    fn channel(&self) -> &str {
+        &self.channel_name
+    }
+
+    fn send_message(&self, text: &str) {
+        self.client
+            .lock()
+            .unwrap()
+            .send_message(self.channel, text, "", false)
+            .unwrap();
+    }
+
+    fn send_file(&self, filename: &str, content: &str) {
+        self.client
+            .lock()
+            .unwrap()
+            .send_file(self.channel, "", content.as_bytes(), filename)
+            .unwrap();
+    }
+
+    fn post_ballot(&self, text: &str, ballot: Ballot) {
+        let client = self.client.lock().unwrap();
+        let posted = client.send_message(self.channel, text, "", false).unwrap();
+        for option in 0..ballot.options() {
+            client
+                .add_reaction(
+                    self.channel,
+                    posted.id,
+                    ReactionEmoji::Unicode(
+                        self.reaction_name(chat::Reaction::Option(option))
+                            .to_string(),
+                    ),
+                )
+                .unwrap();
+        }
+        vote::read_ballots().add(
+            Platform::Discord,
+            &self.channel_name,
+            &posted.id.0.to_string(),
+            ballot,
         );
     }
+
+    fn send_direct_message(&self, user: &User, text: &str) {
+        let client = self.client.lock().unwrap();
+        match user.id.parse().map(UserId) {
+            Ok(id) => match client.create_private_channel(id) {
+                Ok(private_channel) => {
+                    client
+                        .send_message(private_channel.id, text, "", false)
+                        .unwrap();
+                }
+                Err(e) => println!("Can't send a direct message to {}: {}", user.name, e),
+            },
+            Err(_) => println!("Invalid Discord user ID '{}'", user.id),
+        }
+    }
+
+    fn reaction_name(&self, reaction: chat::Reaction) -> &'static str {
+        match reaction {
+            chat::Reaction::Ok => "\u{1f44d}",
+            chat::Reaction::Confirmation => "\u{2705}",
+            chat::Reaction::Option(option) => OPTIONS[option],
+        }
+    }
+
+    fn react(&self, message: &str, reaction: &str) {
+        match message.parse().map(MessageId) {
+            Ok(message) => {
+                self.client
+                    .lock()
+                    .unwrap()
+                    .add_reaction(
+                        self.channel,
+                        message,
+                        ReactionEmoji::Unicode(reaction.to_string()),
+                    )
+                    .unwrap();
+            }
+            Err(_) => println!("Invalid Discord message ID '{}'", message),
+        }
+    }
+
+    fn resolve_user(&self, id: &str) -> Option<User> {
+        let display_names = self.display_names.lock().unwrap();
+        let name = display_names.get(&UserId(id.parse().ok()?))?;
+        Some(User::new(Platform::Discord, id, name))
+    }
+
+    fn to_reminder_message(&self, message: &str) -> String {
+        format!("<@&697181979564834897>: {}", message)
+    }
 }
 
 fn receive_events(handler: &mut Handler) {
@@ -81,9 +206,14 @@ fn receive_events(handler: &mut Handler) {
                                 .collect::<Vec<_>>()
                         );
                     }
+                    for role in server.roles {
+                        handler.role_names.insert(role.id, role.name);
+                    }
+                    let mut display_names = handler.display_names.lock().unwrap();
                     for member in server.members {
+                        handler.roles.insert(member.user.id, member.roles);
                         if let Some(nick) = member.nick {
-                            handler.display_names.insert(member.user.id, nick);
+                            display_names.insert(member.user.id, nick);
                         }
                     }
                 } else if let PossibleServer::Offline(server) = server {
@@ -94,46 +224,55 @@ fn receive_events(handler: &mut Handler) {
             }
 
             Ok(Event::MessageCreate(message)) => {
-                if let Some(channel) = handler.channel {
-                    if channel == message.channel_id {
-                        match parse_message(
-                            &message.content,
-                            if let Some(display_name) =
-                                handler.display_names.get(&message.author.id)
-                            {
+                if Some(message.channel_id) == handler.channel {
+                    let display_names = handler.display_names.lock().unwrap();
+                    let sender = User {
+                        roles: handler
+                            .roles
+                            .get(&message.author.id)
+                            .map(|roles| {
+                                roles
+                                    .iter()
+                                    .filter_map(|role| handler.role_names.get(role).cloned())
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        ..User::new(
+                            Platform::Discord,
+                            &message.author.id.0.to_string(),
+                            if let Some(display_name) = display_names.get(&message.author.id) {
                                 display_name
                             } else {
                                 println!("Missing display name for '{}' (see 'Discord display names' in the readme)",
                                          message.author.name);
                                 &message.author.name
                             },
-                            |s: String| {
-                                handler
-                                    .client
-                                    .lock()
-                                    .unwrap()
-                                    .send_message(channel, &s, "", false)
-                                    .unwrap();
-                            },
-                            &handler.sender,
-                        ) {
-                            Some(Emoji::Ok) => {
-                                handler
-                                    .client
-                                    .lock()
-                                    .unwrap()
-                                    .add_reaction(
-                                        channel,
-                                        message.id,
-                                        ReactionEmoji::Unicode("👍".to_string()),
-                                    )
-                                    .unwrap();
-                            }
-                            _ => {}
-                        }
-                    }
+                        )
+                    };
+                    let mentions = message
+                        .mentions
+                        .iter()
+                        .map(|user| {
+                            User::new(
+                                Platform::Discord,
+                                &user.id.0.to_string(),
+                                display_names.get(&user.id).unwrap_or(&user.name),
+                            )
+                        })
+                        .collect();
+                    let _ = handler.incoming.send(Incoming::Message {
+                        id: message.id.0.to_string(),
+                        sender,
+                        text: message.content,
+                        mentions,
+                    });
                 }
             }
+            Ok(Event::ServerMemberUpdate { user, roles, .. }) => {
+                handler.roles.insert(user.id, roles);
+            }
+            Ok(Event::ReactionAdd(reaction)) => on_reaction(handler, reaction, true),
+            Ok(Event::ReactionRemove(reaction)) => on_reaction(handler, reaction, false),
             Ok(_) => {}
             Err(Error::Closed(code, body)) => {
                 println!("Discord closed with code {:?}: {}", code, body);
@@ -146,49 +285,27 @@ fn receive_events(handler: &mut Handler) {
     }
 }
 
-async fn receive_from_slack(
-    mut receiver: mpsc::UnboundedReceiver<AgendaPoint>,
-    client: Arc<Mutex<discord::Discord>>,
-    channel: Option<ChannelId>,
-) {
-    if let Some(channel) = channel {
-        while let Some(point) = receiver.recv().await {
-            println!("Discord received '{}'", point);
-            client
+fn on_reaction(handler: &Handler, reaction: Reaction, add: bool) {
+    if reaction.user_id == handler.our_id || Some(reaction.channel_id) != handler.channel {
+        return;
+    }
+    if let ReactionEmoji::Unicode(emoji) = reaction.emoji {
+        let id = reaction.user_id.0.to_string();
+        let user = User::new(
+            Platform::Discord,
+            &id,
+            handler
+                .display_names
                 .lock()
                 .unwrap()
-                .send_message(channel, &point.to_add_message(), "", false)
-                .unwrap();
-        }
-    }
-}
-
-async fn handle_reminders(
-    mut reminder: watch::Receiver<ReminderType>,
-    client: Arc<Mutex<discord::Discord>>,
-    channel: Option<ChannelId>,
-) {
-    if let Some(channel) = channel {
-        while reminder.changed().await.is_ok() {
-            let reminder = reminder.borrow();
-            match *reminder {
-                ReminderType::OneHour => {
-                    client
-                        .lock()
-                        .unwrap()
-                        .send_message(
-                            channel,
-                            &format!(
-                                "<@&697181979564834897>: Meeting in one hour!\n{}",
-                                agenda::read_agenda().to_string()
-                            ),
-                            "",
-                            false,
-                        )
-                        .unwrap();
-                }
-                ReminderType::Void => {}
-            }
-        }
+                .get(&reaction.user_id)
+                .unwrap_or(&id),
+        );
+        let _ = handler.incoming.send(Incoming::Reaction {
+            message: reaction.message_id.0.to_string(),
+            user,
+            reaction: emoji,
+            add,
+        });
     }
 }
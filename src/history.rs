@@ -0,0 +1,88 @@
+use crate::agenda::Operation;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// The number of changes that can be undone.
+const MAX_HISTORY: usize = 20;
+
+/// Changes to the agenda that can be undone or redone.
+#[derive(Default, Deserialize, Serialize)]
+pub struct History {
+    undo: Vec<Operation>,
+    redo: Vec<Operation>,
+}
+
+impl History {
+    pub fn write(&self) {
+        fs::write(
+            std::path::Path::new("undo.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize history"),
+        )
+        .expect("Can't write undo.json");
+    }
+
+    pub fn record(&mut self, operation: Operation) {
+        self.undo.push(operation);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self) -> Option<Operation> {
+        let operation = self.undo.pop()?;
+        self.redo.push(operation.clone());
+        Some(operation)
+    }
+
+    pub fn redo(&mut self) -> Option<Operation> {
+        let operation = self.redo.pop()?;
+        self.undo.push(operation.clone());
+        Some(operation)
+    }
+}
+
+pub fn read_history() -> History {
+    match fs::read_to_string("undo.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing undo.json"),
+        Err(_) => History::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(id: u32) -> Operation {
+        Operation::Edit {
+            id,
+            old_title: "old".to_string(),
+            new_title: "new".to_string(),
+        }
+    }
+
+    fn id(operation: Option<Operation>) -> Option<u32> {
+        match operation {
+            Some(Operation::Edit { id, .. }) => Some(id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut history = History::default();
+        for i in 0..MAX_HISTORY as u32 + 5 {
+            history.record(edit(i));
+        }
+        assert_eq!(history.undo.len(), MAX_HISTORY);
+        assert_eq!(id(history.undo()), Some(MAX_HISTORY as u32 + 4));
+        assert_eq!(id(history.undo()), Some(MAX_HISTORY as u32 + 3));
+        assert_eq!(id(history.redo()), Some(MAX_HISTORY as u32 + 3));
+
+        // A new change can't be followed by a redo.
+        history.record(edit(100));
+        assert_eq!(id(history.redo()), None);
+        assert_eq!(id(history.undo()), Some(100));
+    }
+}
@@ -0,0 +1,583 @@
+use crate::{agenda::Agenda, schedule::resolve_local};
+
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use std::{collections::HashMap, fs};
+
+// Minutes.
+const MEETING_LENGTH: i64 = 60;
+
+/// Meetings read from an iCalendar file.
+///
+/// Only the parts of RFC 5545 needed for a recurring meeting are supported:
+/// DTSTART, RRULE (DAILY, WEEKLY and MONTHLY with INTERVAL, BYDAY, COUNT and
+/// UNTIL), EXDATE, RECURRENCE-ID and STATUS:CANCELLED. BYDAY can only have
+/// ordinals, like 2TH for the second Thursday, in monthly rules. Events with
+/// other rules are skipped.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    events: Vec<Event>,
+}
+
+#[derive(Debug)]
+struct Event {
+    uid: Option<String>,
+    timezone: Tz,
+    start: NaiveDateTime,
+    rule: Option<Rule>,
+    exdates: Vec<DateTime<Utc>>,
+    recurrence_id: Option<DateTime<Utc>>,
+    cancelled: bool,
+    // Set if the RRULE couldn't be parsed, so that the event isn't taken for
+    // a single meeting.
+    unsupported: bool,
+}
+
+#[derive(Debug)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug)]
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    // BYDAY, with the ordinal within the month if any, e.g. (Some(-1), Fri)
+    // for the last Friday.
+    weekdays: Vec<(Option<i32>, Weekday)>,
+    count: Option<usize>,
+    until: Option<DateTime<Utc>>,
+}
+
+impl Calendar {
+    pub fn parse(s: &str, default_timezone: Tz) -> Self {
+        let mut events = Vec::new();
+        let mut event: Option<Event> = None;
+        for line in unfold(s) {
+            let (name, params, value) = match split_property(&line) {
+                Some(property) => property,
+                None => continue,
+            };
+            match (name.as_str(), &mut event) {
+                ("BEGIN", None) if value == "VEVENT" => {
+                    event = Some(Event {
+                        uid: None,
+                        timezone: default_timezone,
+                        start: NaiveDateTime::from_timestamp(0, 0),
+                        rule: None,
+                        exdates: Vec::new(),
+                        recurrence_id: None,
+                        cancelled: false,
+                        unsupported: false,
+                    })
+                }
+                ("END", Some(_)) if value == "VEVENT" => events.extend(event.take()),
+                ("UID", Some(event)) => event.uid = Some(value.to_string()),
+                ("DTSTART", Some(event)) => {
+                    if let Some((timezone, start)) =
+                        parse_datetime(value, &params, default_timezone)
+                    {
+                        event.timezone = timezone;
+                        event.start = start;
+                    }
+                }
+                ("RRULE", Some(event)) => {
+                    event.rule = parse_rule(value, default_timezone);
+                    event.unsupported = event.rule.is_none();
+                }
+                ("EXDATE", Some(event)) => event.exdates.extend(
+                    value
+                        .split(',')
+                        .filter_map(|value| parse_datetime(value, &params, default_timezone))
+                        .map(|(timezone, dt)| to_utc(timezone, dt)),
+                ),
+                ("RECURRENCE-ID", Some(event)) => {
+                    event.recurrence_id = parse_datetime(value, &params, default_timezone)
+                        .map(|(timezone, dt)| to_utc(timezone, dt))
+                }
+                ("STATUS", Some(event)) => event.cancelled = value == "CANCELLED",
+                _ => {}
+            }
+        }
+
+        // Events with a RECURRENCE-ID replace one occurrence of the recurring
+        // event with the same UID.
+        let replaced = events
+            .iter()
+            .filter_map(|event| Some((event.uid.clone()?, event.recurrence_id?)))
+            .collect::<Vec<_>>();
+        for event in events.iter_mut().filter(|event| event.rule.is_some()) {
+            for (uid, recurrence_id) in &replaced {
+                if event.uid.as_ref() == Some(uid) {
+                    event.exdates.push(*recurrence_id);
+                }
+            }
+        }
+
+        Self {
+            events: events
+                .into_iter()
+                .filter(|e| !e.cancelled && !e.unsupported)
+                .collect(),
+        }
+    }
+
+    /// The first meeting strictly after `now`.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.events
+            .iter()
+            .filter_map(|event| event.occurrences().find(|dt| *dt > now))
+            .min()
+    }
+
+    /// The last meeting at or before `now`.
+    pub fn previous_before(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.events
+            .iter()
+            .filter_map(|event| event.occurrences().take_while(|dt| *dt <= now).last())
+            .max()
+    }
+}
+
+impl Event {
+    fn occurrences(&self) -> Box<dyn Iterator<Item = DateTime<Utc>> + '_> {
+        let rule = match &self.rule {
+            Some(rule) => rule,
+            None => return Box::new(std::iter::once(to_utc(self.timezone, self.start))),
+        };
+        let start = self.start;
+        let time = start.time();
+        let interval = i64::from(rule.interval.max(1));
+        let dates: Box<dyn Iterator<Item = NaiveDate>> = match rule.frequency {
+            Frequency::Daily => Box::new(
+                (0..)
+                    .map(move |n| start.date() + Duration::days(n * interval))
+                    .filter(move |date| {
+                        rule.weekdays.is_empty()
+                            || rule.weekdays.iter().any(|(_, day)| *day == date.weekday())
+                    }),
+            ),
+            Frequency::Weekly => {
+                let mut weekdays = if rule.weekdays.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    rule.weekdays.iter().map(|(_, day)| *day).collect()
+                };
+                weekdays.sort_by_key(|day| day.num_days_from_monday());
+                let monday =
+                    start.date() - Duration::days(start.weekday().num_days_from_monday().into());
+                Box::new((0..).flat_map(move |n| {
+                    let week = monday + Duration::weeks(n * interval);
+                    weekdays
+                        .clone()
+                        .into_iter()
+                        .map(move |day| week + Duration::days(day.num_days_from_monday().into()))
+                }))
+            }
+            Frequency::Monthly => Box::new((0..).flat_map(move |n| {
+                let month = i64::from(start.month0()) + n * interval;
+                let (year, month) = (start.year() + (month / 12) as i32, (month % 12) as u32 + 1);
+                if rule.weekdays.is_empty() {
+                    NaiveDate::from_ymd_opt(year, month, start.day())
+                        .into_iter()
+                        .collect()
+                } else {
+                    days_in_month(year, month, &rule.weekdays)
+                }
+            })),
+        };
+        // Give up on rules that never produce any more occurrences, e.g.
+        // monthly on the 31st in a month without one.
+        let horizon = start.date() + Duration::days(366 * 100);
+        let occurrences = dates
+            .take_while(move |date| *date < horizon)
+            .map(move |date| date.and_time(time))
+            .filter(move |dt| *dt >= start)
+            .map(move |dt| to_utc(self.timezone, dt))
+            .take(rule.count.unwrap_or(usize::MAX))
+            .take_while(move |dt| rule.until.is_none_or(|until| *dt <= until))
+            .filter(move |dt| !self.exdates.contains(dt));
+        Box::new(occurrences)
+    }
+}
+
+/// The days of a month that match BYDAY, in order.
+fn days_in_month(year: i32, month: u32, weekdays: &[(Option<i32>, Weekday)]) -> Vec<NaiveDate> {
+    let first = NaiveDate::from_ymd(year, month, 1);
+    let next = match month {
+        12 => NaiveDate::from_ymd(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd(year, month + 1, 1),
+    };
+    let days = (next - first).num_days();
+    (0..days)
+        .map(|day| first + Duration::days(day))
+        .filter(|date| {
+            // Which of its weekday in the month the date is, from the start
+            // and from the end.
+            let from_start = date.day0() as i32 / 7 + 1;
+            let from_end = -((days as i32 - 1 - date.day0() as i32) / 7 + 1);
+            weekdays.iter().any(|(ordinal, day)| {
+                *day == date.weekday()
+                    && ordinal.is_none_or(|ordinal| ordinal == from_start || ordinal == from_end)
+            })
+        })
+        .collect()
+}
+
+pub fn read_calendar(path: &str, default_timezone: Tz) -> Calendar {
+    match fs::read_to_string(path) {
+        Ok(s) => Calendar::parse(&s, default_timezone),
+        Err(e) => {
+            println!("Can't read calendar {}: {}", path, e);
+            Calendar::default()
+        }
+    }
+}
+
+/// An iCalendar file with the meeting starting at `start` and the agenda as
+/// its description.
+pub fn render_meeting(start: DateTime<Utc>, agenda: &Agenda) -> String {
+    let format = |dt: DateTime<Utc>| dt.format("%Y%m%dT%H%M%SZ").to_string();
+    [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//LiTHe kod//agenda-bot//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@agenda-bot", format(start)),
+        format!("DTSTAMP:{}", format(Utc::now())),
+        format!("DTSTART:{}", format(start)),
+        format!(
+            "DTEND:{}",
+            format(start + Duration::minutes(MEETING_LENGTH))
+        ),
+        "SUMMARY:Board meeting".to_string(),
+        format!("DESCRIPTION:{}", escape(&agenda.to_string())),
+        "END:VEVENT".to_string(),
+        "END:VCALENDAR".to_string(),
+    ]
+    .iter()
+    .map(|line| fold(line))
+    .collect::<Vec<_>>()
+    .join("")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a line into lines of at most 75 bytes, the opposite of `unfold`.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Join lines that have been folded, i.e. continued on a new line starting
+/// with a space or a tab.
+fn unfold(s: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in s.lines() {
+        match (
+            line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split "NAME;PARAM=VALUE;PARAM=VALUE:VALUE" into its parts.
+fn split_property(line: &str) -> Option<(String, HashMap<String, String>, &str)> {
+    let (name, value) = line.split_once(':')?;
+    let mut parts = name.split(';');
+    let name = parts.next()?.to_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+    Some((name, params, value.trim()))
+}
+
+/// Parse a DATE or DATE-TIME value. Times in UTC are converted to the default
+/// timezone so that recurrences keep the same local time.
+fn parse_datetime(
+    value: &str,
+    params: &HashMap<String, String>,
+    default_timezone: Tz,
+) -> Option<(Tz, NaiveDateTime)> {
+    let timezone = params
+        .get("TZID")
+        .and_then(|tzid| tzid.parse::<Tz>().ok())
+        .unwrap_or(default_timezone);
+    if let Some(utc) = value.strip_suffix('Z') {
+        let dt = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((
+            default_timezone,
+            Utc.from_utc_datetime(&dt)
+                .with_timezone(&default_timezone)
+                .naive_local(),
+        ));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::from_hms(0, 0, 0)))
+        })
+        .map(|dt| (timezone, dt))
+}
+
+fn parse_rule(value: &str, default_timezone: Tz) -> Option<Rule> {
+    let rule = parse_supported_rule(value, default_timezone);
+    if rule.is_none() {
+        println!("Skipping an event with an unsupported RRULE: {}", value);
+    }
+    rule
+}
+
+fn parse_supported_rule(value: &str, default_timezone: Tz) -> Option<Rule> {
+    let mut frequency = None;
+    let mut rule = Rule {
+        frequency: Frequency::Weekly,
+        interval: 1,
+        weekdays: Vec::new(),
+        count: None,
+        until: None,
+    };
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        match key {
+            "FREQ" => {
+                frequency = match value {
+                    "DAILY" => Some(Frequency::Daily),
+                    "WEEKLY" => Some(Frequency::Weekly),
+                    "MONTHLY" => Some(Frequency::Monthly),
+                    _ => None,
+                }
+            }
+            "INTERVAL" => rule.interval = value.parse().ok()?,
+            "COUNT" => rule.count = value.parse().ok(),
+            "UNTIL" => {
+                rule.until = parse_datetime(value, &HashMap::new(), default_timezone)
+                    .map(|(timezone, dt)| to_utc(timezone, dt))
+            }
+            "BYDAY" => {
+                rule.weekdays = value.split(',').map(parse_weekday).collect::<Option<_>>()?
+            }
+            "WKST" => {}
+            _ => return None,
+        }
+    }
+    rule.frequency = frequency?;
+    match rule.frequency {
+        Frequency::Monthly => Some(rule),
+        _ if rule.weekdays.iter().all(|(ordinal, _)| ordinal.is_none()) => Some(rule),
+        _ => None,
+    }
+}
+
+/// Parse a BYDAY value like "TH", "2TH" or "-1FR".
+fn parse_weekday(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let split = value.len().checked_sub(2)?;
+    let weekday = match value.get(split..)? {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    let ordinal = match &value[..split] {
+        "" => None,
+        ordinal => match ordinal.parse::<i32>().ok()? {
+            0 => return None,
+            ordinal => Some(ordinal),
+        },
+    };
+    Some((ordinal, weekday))
+}
+
+fn to_utc(timezone: Tz, dt: NaiveDateTime) -> DateTime<Utc> {
+    resolve_local(&timezone, dt).with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:board-meeting\r
+DTSTART;TZID=Europe/Stockholm:20201008T121500\r
+RRULE:FREQ=WEEKLY;INTERVAL=1;BYDAY=TH;UNTIL=20201231T230000Z\r
+EXDATE;TZID=Europe/Stockholm:20201105T121500,\r
+ 20201112T121500\r
+SUMMARY:Board meeting\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:board-meeting\r
+RECURRENCE-ID;TZID=Europe/Stockholm:20201119T121500\r
+DTSTART;TZID=Europe/Stockholm:20201120T170000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:cancelled\r
+DTSTART:20201110T120000Z\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn stockholm(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        chrono_tz::Europe::Stockholm
+            .ymd(y, m, d)
+            .and_hms(h, min, 0)
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn next_after() {
+        let calendar = Calendar::parse(CALENDAR, chrono_tz::Europe::Stockholm);
+        let next = |dt| calendar.next_after(dt);
+        assert_eq!(
+            next(stockholm(2020, 10, 1, 0, 0)),
+            Some(stockholm(2020, 10, 8, 12, 15))
+        );
+        // Across the change to winter time.
+        assert_eq!(
+            next(stockholm(2020, 10, 22, 13, 0)),
+            Some(stockholm(2020, 10, 29, 12, 15))
+        );
+        // Excluded dates and a moved meeting.
+        assert_eq!(
+            next(stockholm(2020, 10, 29, 13, 0)),
+            Some(stockholm(2020, 11, 20, 17, 0))
+        );
+        assert_eq!(
+            next(stockholm(2020, 11, 20, 17, 0)),
+            Some(stockholm(2020, 11, 26, 12, 15))
+        );
+        assert_eq!(next(stockholm(2020, 12, 31, 13, 0)), None);
+        assert_eq!(
+            calendar.previous_before(stockholm(2020, 11, 18, 0, 0)),
+            Some(stockholm(2020, 10, 29, 12, 15))
+        );
+    }
+
+    #[test]
+    fn count() {
+        let calendar = Calendar::parse(
+            "BEGIN:VEVENT\nDTSTART:20201119T111500Z\nRRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=3\nEND:VEVENT\n",
+            chrono_tz::Europe::Stockholm,
+        );
+        let mut now = stockholm(2020, 11, 1, 0, 0);
+        let mut meetings = Vec::new();
+        while let Some(next) = calendar.next_after(now) {
+            meetings.push(next);
+            now = next;
+        }
+        assert_eq!(
+            meetings,
+            vec![
+                stockholm(2020, 11, 19, 12, 15),
+                stockholm(2020, 11, 30, 12, 15),
+                stockholm(2020, 12, 3, 12, 15),
+            ]
+        );
+    }
+
+    fn meetings(rule: &str, from: DateTime<Utc>, n: usize) -> Vec<DateTime<Utc>> {
+        let calendar = Calendar::parse(
+            &format!(
+                "BEGIN:VEVENT\nDTSTART;TZID=Europe/Stockholm:20201001T121500\nRRULE:{}\nEND:VEVENT\n",
+                rule
+            ),
+            chrono_tz::Europe::Stockholm,
+        );
+        let mut now = from;
+        let mut meetings = Vec::new();
+        while let Some(next) = calendar.next_after(now).filter(|_| meetings.len() < n) {
+            meetings.push(next);
+            now = next;
+        }
+        meetings
+    }
+
+    #[test]
+    fn by_day() {
+        let from = stockholm(2020, 10, 1, 0, 0);
+        assert_eq!(
+            meetings("FREQ=MONTHLY;BYDAY=2TH", from, 3),
+            vec![
+                stockholm(2020, 10, 8, 12, 15),
+                stockholm(2020, 11, 12, 12, 15),
+                stockholm(2020, 12, 10, 12, 15),
+            ]
+        );
+        assert_eq!(
+            meetings("FREQ=MONTHLY;INTERVAL=2;BYDAY=1MO,-1FR", from, 3),
+            vec![
+                stockholm(2020, 10, 5, 12, 15),
+                stockholm(2020, 10, 30, 12, 15),
+                stockholm(2020, 12, 7, 12, 15),
+            ]
+        );
+        assert_eq!(
+            meetings("FREQ=DAILY;BYDAY=MO,TH", from, 3),
+            vec![
+                stockholm(2020, 10, 1, 12, 15),
+                stockholm(2020, 10, 5, 12, 15),
+                stockholm(2020, 10, 8, 12, 15),
+            ]
+        );
+        // Not supported, so no meetings rather than the wrong ones.
+        assert!(meetings("FREQ=MONTHLY;BYMONTHDAY=15", from, 3).is_empty());
+        assert!(meetings("FREQ=WEEKLY;BYDAY=2TH", from, 3).is_empty());
+    }
+
+    #[test]
+    fn render_meeting() {
+        let rendered = super::render_meeting(
+            stockholm(2020, 11, 19, 12, 15),
+            &crate::agenda::Agenda::default(),
+        );
+        assert!(rendered.contains("DTSTART:20201119T111500Z\r\n"));
+        assert!(rendered.contains("DTEND:20201119T121500Z\r\n"));
+        assert!(rendered.lines().all(|line| line.len() <= 76));
+
+        let calendar = Calendar::parse(&rendered, chrono_tz::Europe::Stockholm);
+        assert_eq!(
+            calendar.next_after(stockholm(2020, 11, 19, 0, 0)),
+            Some(stockholm(2020, 11, 19, 12, 15))
+        );
+    }
+
+    #[test]
+    fn fold() {
+        let line = format!("DESCRIPTION:{}", "å".repeat(50));
+        let folded = super::fold(&line);
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(unfold(&folded), vec![line]);
+    }
+}
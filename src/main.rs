@@ -1,23 +1,69 @@
 mod agenda;
+mod archive;
+mod board;
+mod bus;
+mod chat;
+mod config;
 mod discord;
+mod history;
+mod ics;
+mod permission;
+mod poll;
 mod reminder;
+mod rsvp;
+mod schedule;
 mod slack;
+mod todo;
+mod user;
+mod vote;
 
-use crate::agenda::AgendaPoint;
-use crate::reminder::ReminderType;
+use crate::agenda::ExportFormat;
+use crate::bus::Bus;
 use futures::join;
-use tokio::sync::{mpsc, watch};
+use std::{fs, process};
+use tokio::sync::broadcast;
 
 #[tokio::main]
 async fn main() {
-    let (from_discord, to_slack) = mpsc::unbounded_channel::<AgendaPoint>();
-    let (from_slack, to_discord) = mpsc::unbounded_channel::<AgendaPoint>();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("export") {
+        export(&args[1..]);
+        return;
+    }
 
-    let (reminder_sender, reminder_receiver) = watch::channel(ReminderType::Void);
+    let connections = vec![discord::connect().await, slack::connect().await]
+        .into_iter()
+        .flatten()
+        .collect();
 
-    join!(
-        reminder::handle(reminder_sender),
-        discord::handle(from_discord, to_discord, reminder_receiver.clone()),
-        slack::handle(from_slack, to_slack, reminder_receiver),
-    );
+    let reminders = broadcast::channel(reminder::CAPACITY).0;
+    let chats = chat::handle(connections, Bus::default(), &reminders);
+
+    join!(reminder::handle(reminders), chats);
+}
+
+/// `agenda-bot export <md|html> [file]` writes the current agenda to a file
+/// instead of starting the bot.
+fn export(args: &[String]) {
+    let format = match args.first().map(|format| format.parse::<ExportFormat>()) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+        None => {
+            println!("Usage: agenda-bot export <md|html> [file]");
+            process::exit(1);
+        }
+    };
+    let path = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| format!("agenda.{}", format.extension()));
+    fs::write(
+        &path,
+        agenda::read_agenda().export(format, reminder::next_meeting()),
+    )
+    .expect("Can't write exported agenda");
+    println!("Agenda written to {}", path);
 }
@@ -0,0 +1,146 @@
+use crate::user::{Platform, User};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Commands that can be restricted to people with the configured role.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Action {
+    Clear,
+    CloseMeeting,
+    ChangeSchedule,
+    // Removing an item someone else added.
+    RemoveOthers,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Action::Clear => "clear the agenda",
+                Action::CloseMeeting => "close the meeting",
+                Action::ChangeSchedule => "change the schedule",
+                Action::RemoveOthers => "remove items added by someone else",
+            }
+        )
+    }
+}
+
+fn all_actions() -> Vec<Action> {
+    vec![
+        Action::Clear,
+        Action::CloseMeeting,
+        Action::ChangeSchedule,
+        Action::RemoveOthers,
+    ]
+}
+
+// The role of the Slack users that are allowed when no role is set.
+const ALLOWED_USERS: &str = "the allowed users";
+
+/// Who may do the restricted actions. Everyone may if neither a role nor
+/// allowed Slack users are set.
+#[derive(Deserialize, Serialize)]
+pub struct Permissions {
+    // The name of the Discord role. Slack users in `slack_usergroup` are
+    // given it as well.
+    #[serde(default)]
+    role: Option<String>,
+    // The ID of a Slack user group.
+    #[serde(default)]
+    pub slack_usergroup: Option<String>,
+    // Slack user IDs that are allowed regardless of user group.
+    #[serde(default)]
+    slack_users: Vec<String>,
+    #[serde(default = "all_actions")]
+    restricted: Vec<Action>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            role: None,
+            slack_usergroup: None,
+            slack_users: Vec::new(),
+            restricted: all_actions(),
+        }
+    }
+}
+
+impl Permissions {
+    /// The role that may do the restricted actions, if any. Without a
+    /// configured role only the allowed Slack users may.
+    pub fn role(&self) -> Option<&str> {
+        match &self.role {
+            Some(role) => Some(role),
+            None if self.slack_usergroup.is_some() || !self.slack_users.is_empty() => {
+                Some(ALLOWED_USERS)
+            }
+            None => None,
+        }
+    }
+
+    pub fn check(&self, user: &User, action: Action) -> Result<(), String> {
+        let role = match self.role() {
+            Some(role) if self.restricted.contains(&action) => role,
+            _ => return Ok(()),
+        };
+        if user.roles.iter().any(|r| r == role)
+            || (user.platform == Platform::Slack && self.slack_users.contains(&user.id))
+        {
+            Ok(())
+        } else {
+            Err(format!("Only {} can {}", role, action))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let user = |platform, id: &str, roles: &[&str]| User {
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            ..User::new(platform, id, "Alice")
+        };
+        assert!(Permissions::default()
+            .check(&user(Platform::Discord, "1", &[]), Action::Clear)
+            .is_ok());
+
+        let permissions: Permissions = serde_json::from_str(
+            r#"{ "role": "Styrelsen", "slack_users": ["U1"], "restricted": ["Clear"] }"#,
+        )
+        .unwrap();
+        assert!(permissions
+            .check(&user(Platform::Discord, "1", &["Styrelsen"]), Action::Clear)
+            .is_ok());
+        assert!(permissions
+            .check(&user(Platform::Slack, "U1", &[]), Action::Clear)
+            .is_ok());
+        assert_eq!(
+            permissions.check(&user(Platform::Discord, "2", &["Medlem"]), Action::Clear),
+            Err("Only Styrelsen can clear the agenda".to_string())
+        );
+        assert!(permissions
+            .check(&user(Platform::Discord, "2", &[]), Action::CloseMeeting)
+            .is_ok());
+
+        // Only allowed Slack users, without a role
+        let permissions: Permissions =
+            serde_json::from_str(r#"{ "slack_users": ["U1"] }"#).unwrap();
+        assert!(permissions
+            .check(&user(Platform::Slack, "U1", &[]), Action::Clear)
+            .is_ok());
+        assert_eq!(
+            permissions.check(&user(Platform::Slack, "U2", &[]), Action::Clear),
+            Err("Only the allowed users can clear the agenda".to_string())
+        );
+        assert!(permissions
+            .check(&user(Platform::Discord, "1", &[]), Action::Clear)
+            .is_err());
+    }
+}
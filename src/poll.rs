@@ -0,0 +1,232 @@
+use crate::{config::Config, user::User, vote::Ballot};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Poll {
+    id: u32,
+    question: String,
+    options: Vec<String>,
+    author: String,
+    #[serde(default)]
+    closes: Option<DateTime<Utc>>,
+    #[serde(default)]
+    closed: bool,
+    // Votes from both platforms, as (user, option).
+    #[serde(default)]
+    votes: Vec<(User, usize)>,
+}
+
+impl Poll {
+    pub fn ballot(&self) -> Ballot {
+        Ballot::Poll {
+            id: self.id,
+            options: self.options.len(),
+        }
+    }
+
+    pub fn to_ballot_message(&self) -> String {
+        let mut s = format!("Poll #{} by {}: {}", self.id, self.author, self.question);
+        for (i, option) in self.options.iter().enumerate() {
+            s.push_str(&format!("\n{}. {}", i + 1, option));
+        }
+        match self.closes {
+            Some(closes) => s.push_str(&format!(
+                "\nReact with the numbers of your choices. Closes {}.",
+                closes.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            )),
+            None => s.push_str(&format!(
+                "\nReact with the numbers of your choices. Closed with !poll-close {}.",
+                self.id
+            )),
+        }
+        s
+    }
+
+    /// The number of votes for each option. Someone who voted on both
+    /// platforms is only counted once if they are a board member with both
+    /// their users in `config`.
+    fn results(&self, config: &Config) -> Vec<usize> {
+        (0..self.options.len())
+            .map(|option| {
+                let mut voters: Vec<&User> = Vec::new();
+                for (user, _) in self.votes.iter().filter(|(_, vote)| *vote == option) {
+                    if !voters.iter().any(|voter| config.same_person(voter, user)) {
+                        voters.push(user);
+                    }
+                }
+                voters.len()
+            })
+            .collect()
+    }
+
+    pub fn to_result_message(&self, config: &Config) -> String {
+        let mut s = format!("Poll #{} '{}' closed:", self.id, self.question);
+        for (option, votes) in self.options.iter().zip(self.results(config)) {
+            s.push_str(&format!("\n{}: {}", option, votes));
+        }
+        s
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Polls {
+    polls: Vec<Poll>,
+    #[serde(default)]
+    last_id: u32,
+}
+
+impl Polls {
+    fn write(&self) {
+        fs::write(
+            std::path::Path::new("polls.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize polls"),
+        )
+        .expect("Can't write polls.json");
+    }
+
+    pub fn add(
+        &mut self,
+        question: &str,
+        options: Vec<String>,
+        author: &str,
+        closes: Option<DateTime<Utc>>,
+    ) -> Poll {
+        self.last_id += 1;
+        let poll = Poll {
+            id: self.last_id,
+            question: question.to_string(),
+            options,
+            author: author.to_string(),
+            closes,
+            closed: false,
+            votes: Vec::new(),
+        };
+        self.polls.push(poll.clone());
+        self.write();
+        poll
+    }
+
+    /// Add or remove `user`'s vote for an option. Votes on closed polls are
+    /// ignored.
+    pub fn vote(&mut self, id: u32, option: usize, user: User, add: bool) {
+        if let Some(poll) = self
+            .polls
+            .iter_mut()
+            .find(|poll| poll.id == id && !poll.closed)
+        {
+            poll.votes.retain(|(voter, vote)| {
+                *vote != option || (voter.platform, &voter.id) != (user.platform, &user.id)
+            });
+            if add {
+                poll.votes.push((user, option));
+            }
+            self.write();
+        }
+    }
+
+    pub fn close(&mut self, id: u32) -> Option<Poll> {
+        let poll = self
+            .polls
+            .iter_mut()
+            .find(|poll| poll.id == id && !poll.closed)?;
+        poll.closed = true;
+        let poll = poll.clone();
+        self.write();
+        Some(poll)
+    }
+
+    /// Close the polls whose closing time has passed.
+    pub fn close_due(&mut self, now: DateTime<Utc>) -> Vec<Poll> {
+        let due = self
+            .polls
+            .iter()
+            .filter(|poll| !poll.closed && poll.closes.is_some_and(|closes| closes <= now))
+            .map(|poll| poll.id)
+            .collect::<Vec<_>>();
+        due.into_iter().filter_map(|id| self.close(id)).collect()
+    }
+}
+
+/// Parse `"<question>" option 1 | option 2 [until <YYYY-MM-DD HH:MM>]` into
+/// the question, the options and the closing time.
+pub fn parse_poll(s: &str) -> Option<(&str, Vec<String>, Option<NaiveDateTime>)> {
+    let (question, rest) = s.trim().strip_prefix('"')?.split_once('"')?;
+    let (rest, closes) = match rest.rsplit_once(" until ") {
+        Some((options, closes)) => match crate::schedule::parse_datetime(closes) {
+            Some(closes) => (options, Some(closes)),
+            // "until" was part of an option
+            None => (rest, None),
+        },
+        None => (rest, None),
+    };
+    let options = rest
+        .split('|')
+        .map(|option| option.trim().to_string())
+        .filter(|option| !option.is_empty())
+        .collect::<Vec<_>>();
+    if question.trim().is_empty() || options.len() < 2 {
+        return None;
+    }
+    Some((question.trim(), options, closes))
+}
+
+pub fn read_polls() -> Polls {
+    match fs::read_to_string("polls.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing polls.json"),
+        Err(_) => Polls::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::Platform;
+
+    #[test]
+    fn parse_poll() {
+        let (question, options, closes) =
+            super::parse_poll(r#""Where to eat?" Pizza | Sushi | Tacos until 2020-11-26 12:00"#)
+                .unwrap();
+        assert_eq!(question, "Where to eat?");
+        assert_eq!(options, vec!["Pizza", "Sushi", "Tacos"]);
+        assert_eq!(closes, crate::schedule::parse_datetime("2020-11-26 12:00"));
+        let (_, options, closes) = super::parse_poll(r#""When?" Now | Wait until Friday"#).unwrap();
+        assert_eq!(options, vec!["Now", "Wait until Friday"]);
+        assert_eq!(closes, None);
+        assert!(super::parse_poll(r#""Where to eat?" Pizza"#).is_none());
+        assert!(super::parse_poll("Where to eat? Pizza | Sushi").is_none());
+    }
+
+    #[test]
+    fn results() {
+        let config: Config = serde_json::from_str(
+            r#"{ "board": [{ "name": "Alice", "slack": "U1", "discord": "1" }] }"#,
+        )
+        .unwrap();
+        let user = |platform, id: &str, name: &str| User::new(platform, id, name);
+        let poll = Poll {
+            id: 1,
+            question: "Where to eat?".to_string(),
+            options: vec!["Pizza".to_string(), "Sushi".to_string()],
+            author: "Gustav".to_string(),
+            closes: None,
+            closed: false,
+            votes: vec![
+                (user(Platform::Slack, "U1", "Alice"), 0),
+                (user(Platform::Discord, "1", "Ali"), 0),
+                (user(Platform::Discord, "2", "Bob"), 0),
+                (user(Platform::Discord, "2", "Bob"), 1),
+                // Someone else with the same name as Bob
+                (user(Platform::Slack, "U2", "Bob"), 1),
+            ],
+        };
+        assert_eq!(poll.results(&config), vec![2, 2]);
+        assert_eq!(
+            poll.to_result_message(&config),
+            "Poll #1 'Where to eat?' closed:\nPizza: 2\nSushi: 2"
+        );
+    }
+}
@@ -1,25 +1,233 @@
-use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
-use serde::{Deserialize, Serialize};
-use std::fs;
-use tokio::sync::watch;
+use crate::{
+    agenda, config, poll, rsvp, schedule, todo,
+    user::{Platform, User},
+};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+use std::{convert::TryFrom, fs};
+use tokio::sync::broadcast;
+
+// Platforms that fall further behind than this miss the oldest reminders.
+pub const CAPACITY: usize = 16;
+
+#[derive(Clone, Debug)]
 pub enum ReminderType {
-    // Different types of reminders are possible.
-    // e.g. different reminders for the day before and one hour before.
-    Void,
-    OneHour, //TODO struct instead
+    Meeting {
+        message: String,
+        target: Target,
+    },
+    MeetingClosed(NaiveDate),
+    // Posted to both platforms, with a direct message to each user.
+    ActionItems {
+        message: String,
+        direct_messages: Vec<(User, String)>,
+    },
+    // The result of a poll that has reached its closing time.
+    PollClosed(String),
+}
+
+/// When a reminder is sent, relative to the meeting.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Offset {
+    // Minutes before the meeting.
+    Before(i64),
+    // At a time of day some days before the meeting.
+    DaysBefore { days: i64, time: NaiveTime },
+}
+
+impl Default for Offset {
+    fn default() -> Self {
+        Offset::Before(60)
+    }
+}
+
+impl Offset {
+    fn fire_time(&self, meeting: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Offset::Before(minutes) => meeting - Duration::minutes(*minutes),
+            Offset::DaysBefore { days, time } => (meeting.date() - Duration::days(*days))
+                .and_time(*time)
+                .unwrap_or(meeting - Duration::days(*days)),
+        }
+    }
+}
+
+/// Where a reminder is sent, written as "Both" for every platform or as the
+/// name of one platform.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    #[default]
+    Both,
+    Only(Platform),
+}
+
+impl Target {
+    pub fn includes(self, platform: Platform) -> bool {
+        match self {
+            Target::Both => true,
+            Target::Only(only) => platform == only,
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = serde::de::value::Error;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if name == "Both" {
+            Ok(Target::Both)
+        } else {
+            Platform::deserialize(name.into_deserializer()).map(Target::Only)
+        }
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Both => "Both".to_string(),
+            Target::Only(platform) => format!("{:?}", platform),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Reminder {
-    reminder_type: ReminderType,
+    #[serde(default)]
+    offset: Offset,
+    // "{agenda}", "{todos}", "{rsvp}" and "{time}" are replaced with the
+    // current agenda, the open action items, who is coming and the time of
+    // the meeting. Lines that only had an empty placeholder are left out.
+    #[serde(default = "default_message")]
+    message: String,
+    #[serde(default)]
+    target: Target,
     last_fire: DateTime<Local>,
 }
 
+fn default_message() -> String {
+    "Meeting in one hour!\n{agenda}\n{todos}\n{rsvp}".to_string()
+}
+
+impl Reminder {
+    fn to_message(&self, meeting: DateTime<Local>) -> String {
+        let time = meeting.format("%A %H:%M").to_string();
+        let agenda = agenda::read_agenda().to_string();
+        let todos = todos_message();
+        let rsvp = rsvp::read_rsvps(meeting).summary(&config::read_config());
+        self.message
+            .lines()
+            .filter_map(|line| {
+                let replaced = line
+                    .replace("{time}", &time)
+                    .replace("{agenda}", &agenda)
+                    .replace("{todos}", &todos)
+                    .replace("{rsvp}", &rsvp);
+                if replaced.is_empty() && !line.is_empty() {
+                    None
+                } else {
+                    Some(replaced)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn todos_message() -> String {
+    let todos = todo::read_todos();
+    if todos.open().next().is_none() {
+        return "".to_string();
+    }
+    format!("Open action items:\n{}", todos)
+}
+
+/// Close the meeting automatically some time after it has started.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AutoClose {
+    // Minutes after the start of the meeting.
+    after: i64,
+    #[serde(default)]
+    last_close: Option<DateTime<Local>>,
+}
+
+/// Remind people of their overdue and upcoming action items once a week.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Nag {
+    weekday: Weekday,
+    time: NaiveTime,
+    // Action items due within this many days are included.
+    #[serde(default = "default_nag_days")]
+    days: i64,
+    // Also send each person their action items in a direct message, if they
+    // were mentioned when the action item was added.
+    #[serde(default)]
+    direct_message: bool,
+    #[serde(default)]
+    last_fire: Option<DateTime<Local>>,
+}
+
+fn default_nag_days() -> i64 {
+    7
+}
+
+impl Nag {
+    /// The latest time the nag should have been sent at before `now`.
+    fn fire_time(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let days_since =
+            (now.weekday().num_days_from_monday() + 7 - self.weekday.num_days_from_monday()) % 7;
+        let fire = (now.date() - Duration::days(days_since.into()))
+            .and_time(self.time)
+            .unwrap_or(now);
+        if fire > now {
+            fire - Duration::weeks(1)
+        } else {
+            fire
+        }
+    }
+
+    fn to_reminder(&self, today: NaiveDate) -> Option<ReminderType> {
+        let todos = todo::read_todos();
+        let due_soon = todos.due_soon(today, self.days);
+        if due_soon.is_empty() {
+            return None;
+        }
+        let messages = due_soon
+            .iter()
+            .map(|(assignee, todos)| {
+                (
+                    todos.iter().find_map(|todo| todo.assignee_user()),
+                    todo::to_nag_message(assignee, todos, today),
+                )
+            })
+            .collect::<Vec<_>>();
+        Some(ReminderType::ActionItems {
+            message: messages
+                .iter()
+                .map(|(_, message)| message.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            direct_messages: if self.direct_message {
+                messages
+                    .iter()
+                    .filter_map(|(user, message)| Some(((*user)?.clone(), message.clone())))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Reminders {
     reminders: Vec<Reminder>,
+    #[serde(default)]
+    auto_close: Option<AutoClose>,
+    #[serde(default)]
+    nag: Option<Nag>,
 }
 
 impl Reminders {
@@ -32,77 +240,187 @@ impl Reminders {
     }
 }
 
-pub async fn handle(sender: watch::Sender<ReminderType>) {
+pub async fn handle(sender: broadcast::Sender<ReminderType>) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(1000));
 
     loop {
         let now = Local::now();
-        let next = next_meeting();
         let mut reminders = read_reminders();
-        for mut reminder in &mut reminders.reminders {
-            match reminder.reminder_type {
-                ReminderType::OneHour => {
-                    if in_remind_zone(now, next) && !in_remind_zone(reminder.last_fire, next) {
-                        sender.send(ReminderType::OneHour).unwrap();
-                        reminder.last_fire = now;
+        if let Some(next) = next_meeting() {
+            for reminder in &mut reminders.reminders {
+                let fire = reminder.offset.fire_time(next);
+                if in_remind_zone(now, fire, next)
+                    && !in_remind_zone(reminder.last_fire, fire, next)
+                {
+                    send(
+                        &sender,
+                        ReminderType::Meeting {
+                            message: reminder.to_message(next),
+                            target: reminder.target,
+                        },
+                    );
+                    reminder.last_fire = now;
+                }
+            }
+        }
+        if let (Some(auto_close), Some(meeting)) = (&mut reminders.auto_close, previous_meeting()) {
+            let close = meeting + Duration::minutes(auto_close.after);
+            match auto_close.last_close {
+                // Don't close a meeting that was held before auto close was
+                // turned on.
+                None => auto_close.last_close = Some(now),
+                Some(last_close) if now >= close && last_close < close => {
+                    let date = meeting.date().naive_local();
+                    // Unless it was already closed by hand
+                    if agenda::close_meeting(date) {
+                        send(&sender, ReminderType::MeetingClosed(date));
+                    }
+                    auto_close.last_close = Some(now);
+                }
+                _ => {}
+            }
+        }
+        if let Some(nag) = &mut reminders.nag {
+            let fire = nag.fire_time(now);
+            match nag.last_fire {
+                // Don't send a nag that was due before it was turned on.
+                None => nag.last_fire = Some(now),
+                Some(last_fire) if last_fire < fire => {
+                    if let Some(reminder) = nag.to_reminder(now.date().naive_local()) {
+                        send(&sender, reminder);
                     }
+                    nag.last_fire = Some(now);
                 }
                 _ => {}
             }
         }
+        let config = config::read_config();
+        for poll in poll::read_polls().close_due(Utc::now()) {
+            send(
+                &sender,
+                ReminderType::PollClosed(poll.to_result_message(&config)),
+            );
+        }
         reminders.write();
         interval.tick().await;
     }
 }
 
+fn send(sender: &broadcast::Sender<ReminderType>, reminder: ReminderType) {
+    // Fails only if no platform is connected, in which case nobody needs it.
+    let _ = sender.send(reminder);
+}
+
 fn read_reminders() -> Reminders {
     match fs::read_to_string("reminders.json") {
         Ok(s) => serde_json::from_str(&s).expect("Error parsing reminders.json"),
         Err(_) => Reminders {
             reminders: vec![Reminder {
-                reminder_type: ReminderType::OneHour,
+                offset: Offset::default(),
+                message: default_message(),
+                target: Target::Both,
                 last_fire: Local::now(),
             }],
+            auto_close: None,
+            nag: None,
         },
     }
 }
 
-fn in_remind_zone(dt: DateTime<Local>, meeting: DateTime<Local>) -> bool {
-    // Wether we're in a "send reminder"-zone.
-    // Currently implemented as "are we 1 hour before?".
-    ((meeting - Duration::hours(1))..meeting).contains(&dt)
+fn in_remind_zone(dt: DateTime<Local>, fire: DateTime<Local>, meeting: DateTime<Local>) -> bool {
+    // Wether we're in a "send reminder"-zone, between the time the reminder
+    // should be sent and the meeting.
+    (fire..meeting).contains(&dt)
 }
 
-fn next_meeting() -> DateTime<Local> {
-    // Check current datetime and calculate when the next meeting is.
-    let now = Local::now();
-    let meeting_time = NaiveTime::from_hms(12, 15, 00);
-    let meeting = match Datelike::weekday(&now) {
-        Weekday::Thu => {
-            // same day as meeting.
-            // next week if meeting has occured.
-            let date_delta = Duration::weeks(if now.time() < meeting_time { 0 } else { 1 });
-            (now.date() + date_delta).and_time(meeting_time).unwrap()
-        }
-        _ => {
-            let dow_index: i64 = now.date().weekday().num_days_from_monday().into();
-            let date_delta = Duration::days((3 - dow_index).rem_euclid(7));
-            (now.date() + date_delta).and_time(meeting_time).unwrap()
-        }
-    };
-    assert!(meeting.weekday() == Weekday::Thu);
-    meeting
+pub fn next_meeting() -> Option<DateTime<Local>> {
+    config::read_config()
+        .schedule
+        .next_after(&schedule::read_overrides(), Utc::now())
+        .map(|meeting| meeting.with_timezone(&Local))
+}
+
+pub fn previous_meeting() -> Option<DateTime<Local>> {
+    config::read_config()
+        .schedule
+        .previous_before(&schedule::read_overrides(), Utc::now())
+        .map(|meeting| meeting.with_timezone(&Local))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn in_remind_zone() {
         let now = Local::now();
-        assert!(super::in_remind_zone(now, now + Duration::minutes(30)));
-        assert!(!super::in_remind_zone(now, now + Duration::hours(2)));
-        assert!(!super::in_remind_zone(now, now - Duration::minutes(30)));
+        let zone = |meeting| super::in_remind_zone(now, meeting - Duration::hours(1), meeting);
+        assert!(zone(now + Duration::minutes(30)));
+        assert!(!zone(now + Duration::hours(2)));
+        assert!(!zone(now - Duration::minutes(30)));
+    }
+
+    #[test]
+    fn fire_time() {
+        let meeting = Local.ymd(2020, 11, 19).and_hms(12, 15, 0);
+        assert_eq!(
+            Offset::Before(10).fire_time(meeting),
+            Local.ymd(2020, 11, 19).and_hms(12, 5, 0)
+        );
+        assert_eq!(
+            Offset::DaysBefore {
+                days: 1,
+                time: NaiveTime::from_hms(18, 0, 0)
+            }
+            .fire_time(meeting),
+            Local.ymd(2020, 11, 18).and_hms(18, 0, 0)
+        );
+    }
+
+    #[test]
+    fn nag_fire_time() {
+        let nag = Nag {
+            weekday: Weekday::Mon,
+            time: NaiveTime::from_hms(9, 0, 0),
+            days: 7,
+            direct_message: false,
+            last_fire: None,
+        };
+        // Wednesday
+        assert_eq!(
+            nag.fire_time(Local.ymd(2020, 11, 25).and_hms(12, 0, 0)),
+            Local.ymd(2020, 11, 23).and_hms(9, 0, 0)
+        );
+        // Monday, before and after the nag
+        assert_eq!(
+            nag.fire_time(Local.ymd(2020, 11, 23).and_hms(8, 0, 0)),
+            Local.ymd(2020, 11, 16).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            nag.fire_time(Local.ymd(2020, 11, 23).and_hms(9, 30, 0)),
+            Local.ymd(2020, 11, 23).and_hms(9, 0, 0)
+        );
+    }
+
+    #[test]
+    fn target() {
+        let target: Target = serde_json::from_str(r#""Slack""#).unwrap();
+        assert!(target.includes(Platform::Slack));
+        assert!(!target.includes(Platform::Discord));
+        assert_eq!(serde_json::to_string(&target).unwrap(), r#""Slack""#);
+        let target: Target = serde_json::from_str(r#""Both""#).unwrap();
+        assert!(target.includes(Platform::Discord));
+        assert!(serde_json::from_str::<Target>(r#""Email""#).is_err());
+    }
+
+    #[test]
+    fn old_reminder() {
+        let reminder: Reminder = serde_json::from_str(
+            r#"{ "reminder_type": "OneHour", "last_fire": "2020-11-19T11:15:00+01:00" }"#,
+        )
+        .unwrap();
+        assert!(matches!(reminder.offset, Offset::Before(60)));
+        assert_eq!(reminder.target, Target::Both);
     }
 }
@@ -0,0 +1,217 @@
+use crate::{config::Config, user::User};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Answer {
+    Yes,
+    Maybe,
+    No,
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Answer::Yes => "Yes",
+                Answer::Maybe => "Maybe",
+                Answer::No => "No",
+            }
+        )
+    }
+}
+
+impl FromStr for Answer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "yes" => Ok(Answer::Yes),
+            "maybe" => Ok(Answer::Maybe),
+            "no" => Ok(Answer::No),
+            _ => Err("Usage: !rsvp yes|no|maybe".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Response {
+    user: User,
+    answer: Answer,
+}
+
+/// Who is coming to the next meeting.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Rsvps {
+    meeting: Option<DateTime<Local>>,
+    responses: Vec<Response>,
+}
+
+impl Rsvps {
+    fn write(&self) {
+        fs::write(
+            std::path::Path::new("rsvps.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize RSVPs"),
+        )
+        .expect("Can't write rsvps.json");
+    }
+
+    /// Set `user`'s answer, replacing any earlier answer from them on either
+    /// platform.
+    pub fn respond(&mut self, user: User, answer: Answer, config: &Config) {
+        self.set_answer(user, answer, config);
+        self.write();
+    }
+
+    fn set_answer(&mut self, user: User, answer: Answer, config: &Config) {
+        self.responses
+            .retain(|response| !config.same_person(&response.user, &user));
+        self.responses.push(Response { user, answer });
+    }
+
+    /// The number of people coming, counting only board members if there is
+    /// a board.
+    fn attending(&self, config: &Config) -> usize {
+        self.responses
+            .iter()
+            .filter(|response| response.answer == Answer::Yes)
+            .filter(|response| config.board.is_empty() || config.member(&response.user).is_some())
+            .count()
+    }
+
+    /// The names of those who answered `answer`, as on the board if they are
+    /// members.
+    fn answered<'a>(&'a self, answer: Answer, config: &'a Config) -> impl Iterator<Item = &'a str> {
+        self.responses
+            .iter()
+            .filter(move |response| response.answer == answer)
+            .map(move |response| match config.member(&response.user) {
+                Some(member) => member.name.as_str(),
+                None => response.user.name.as_str(),
+            })
+    }
+
+    /// Who is coming, who isn't and who hasn't answered. Empty if nobody has
+    /// answered and there is no board or quorum to answer to.
+    pub fn summary(&self, config: &Config) -> String {
+        if self.responses.is_empty() && config.board.is_empty() && config.quorum().is_none() {
+            return "".to_string();
+        }
+        let mut s = match self.meeting {
+            Some(meeting) => format!(
+                "Attending the meeting on {}:",
+                meeting.format("%Y-%m-%d %H:%M")
+            ),
+            None => "Attending the next meeting:".to_string(),
+        };
+        for answer in &[Answer::Yes, Answer::Maybe, Answer::No] {
+            let names = self.answered(*answer, config).collect::<Vec<_>>();
+            s.push_str(&format!(
+                "\n{} ({}): {}",
+                answer,
+                names.len(),
+                names.join(", ")
+            ));
+        }
+        let missing = config
+            .board
+            .iter()
+            .filter(|member| {
+                !self
+                    .responses
+                    .iter()
+                    .any(|response| member.is(&response.user))
+            })
+            .map(|member| member.name.as_str())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            s.push_str(&format!("\nNo answer: {}", missing.join(", ")));
+        }
+        if let Some(warning) = self.quorum_warning(config) {
+            s.push_str(&format!("\n{}", warning));
+        }
+        s
+    }
+
+    fn quorum_warning(&self, config: &Config) -> Option<String> {
+        match config.quorum() {
+            Some(quorum) if self.attending(config) < quorum => Some(format!(
+                "Warning: only {} of the {} needed for quorum have confirmed",
+                self.attending(config),
+                quorum
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// The responses for `meeting`. Responses for earlier meetings are dropped.
+pub fn read_rsvps(meeting: DateTime<Local>) -> Rsvps {
+    let rsvps: Rsvps = match fs::read_to_string("rsvps.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing rsvps.json"),
+        Err(_) => Rsvps::default(),
+    };
+    if rsvps.meeting == Some(meeting) {
+        rsvps
+    } else {
+        Rsvps {
+            meeting: Some(meeting),
+            responses: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::Platform;
+    use chrono::TimeZone;
+
+    #[test]
+    fn summary() {
+        let user = |platform, id: &str, name: &str| User::new(platform, id, name);
+        let config: Config = serde_json::from_str(
+            r#"{
+                "board": [
+                    { "name": "Alice", "slack": "U1", "discord": "1" },
+                    { "name": "Bob", "discord": "2" },
+                    { "name": "Carol", "slack": "U3" }
+                ],
+                "quorum": "Majority"
+            }"#,
+        )
+        .unwrap();
+        let mut rsvps = Rsvps {
+            meeting: Some(Local.ymd(2020, 11, 26).and_hms(12, 15, 0)),
+            responses: Vec::new(),
+        };
+        rsvps.set_answer(user(Platform::Slack, "U1", "alice"), Answer::No, &config);
+        rsvps.set_answer(user(Platform::Discord, "2", "Bob"), Answer::Maybe, &config);
+        // The same member on the other platform
+        rsvps.set_answer(user(Platform::Discord, "1", "Ali"), Answer::Yes, &config);
+        // Not a board member
+        rsvps.set_answer(user(Platform::Slack, "U9", "Dave"), Answer::Yes, &config);
+        assert_eq!(
+            rsvps.summary(&config),
+            "Attending the meeting on 2020-11-26 12:15:\n\
+             Yes (2): Alice, Dave\n\
+             Maybe (1): Bob\n\
+             No (0): \n\
+             No answer: Carol\n\
+             Warning: only 1 of the 2 needed for quorum have confirmed"
+        );
+
+        let rsvps = Rsvps {
+            meeting: None,
+            responses: Vec::new(),
+        };
+        assert_eq!(rsvps.summary(&Config::default()), "");
+        assert!(rsvps
+            .summary(&config)
+            .starts_with("Attending the next meeting:"));
+    }
+}
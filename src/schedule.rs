@@ -0,0 +1,512 @@
+use crate::ics::{self, Calendar};
+
+use chrono::{
+    offset::LocalResult, DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs};
+
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// When the board meets, either every week on some weekdays or according to
+/// an iCalendar file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Schedule {
+    #[serde(default = "thursday")]
+    weekdays: Vec<Weekday>,
+    #[serde(default = "quarter_past_twelve")]
+    time: NaiveTime,
+    #[serde(default = "stockholm")]
+    timezone: Tz,
+    // Meet every n:th week.
+    #[serde(default = "one")]
+    every: u32,
+    // A date in a week with a meeting. Only needed if `every` is more than 1.
+    #[serde(default)]
+    start: Option<NaiveDate>,
+    // Path to an iCalendar file with the meetings. Replaces the weekly
+    // schedule if set.
+    #[serde(default)]
+    calendar: Option<String>,
+}
+
+fn thursday() -> Vec<Weekday> {
+    vec![Weekday::Thu]
+}
+
+fn quarter_past_twelve() -> NaiveTime {
+    NaiveTime::from_hms(12, 15, 00)
+}
+
+fn stockholm() -> Tz {
+    chrono_tz::Europe::Stockholm
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            weekdays: thursday(),
+            time: quarter_past_twelve(),
+            timezone: stockholm(),
+            every: 1,
+            start: None,
+            calendar: None,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn timezone(&self) -> &Tz {
+        &self.timezone
+    }
+
+    /// The first meeting strictly after `now`.
+    pub fn next_after(&self, overrides: &Overrides, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        let regular = match self.read_calendar() {
+            Some(calendar) => {
+                let mut now = now;
+                loop {
+                    let meeting = calendar.next_after(now)?.with_timezone(&self.timezone);
+                    if !overrides.replaces(meeting.date().naive_local()) {
+                        break Some(meeting);
+                    }
+                    now = meeting.with_timezone(&Utc);
+                }
+            }
+            None => {
+                let today = now.with_timezone(&self.timezone).date().naive_local();
+                (0..=self.search_days(overrides))
+                    .filter_map(|days| self.meeting_on(overrides, today + Duration::days(days)))
+                    .find(|meeting| *meeting > now)
+            }
+        };
+        let extra = overrides
+            .extra_meetings(&self.timezone)
+            .filter(|meeting| *meeting > now)
+            .min();
+        regular.into_iter().chain(extra).min()
+    }
+
+    /// The last meeting at or before `now`.
+    pub fn previous_before(
+        &self,
+        overrides: &Overrides,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Tz>> {
+        let regular = match self.read_calendar() {
+            Some(calendar) => {
+                let mut now = now;
+                loop {
+                    let meeting = calendar.previous_before(now)?.with_timezone(&self.timezone);
+                    if !overrides.replaces(meeting.date().naive_local()) {
+                        break Some(meeting);
+                    }
+                    now = meeting.with_timezone(&Utc) - Duration::seconds(1);
+                }
+            }
+            None => {
+                let today = now.with_timezone(&self.timezone).date().naive_local();
+                (0..=self.search_days(overrides))
+                    .filter_map(|days| self.meeting_on(overrides, today - Duration::days(days)))
+                    .find(|meeting| *meeting <= now)
+            }
+        };
+        let extra = overrides
+            .extra_meetings(&self.timezone)
+            .filter(|meeting| *meeting <= now)
+            .max();
+        regular.into_iter().chain(extra).max()
+    }
+
+    /// Whether there is a meeting, regular or not, on `date`.
+    pub fn has_meeting_on(&self, overrides: &Overrides, date: NaiveDate) -> bool {
+        let regular = match self.read_calendar() {
+            Some(calendar) => {
+                let midnight = resolve_local(&self.timezone, date.and_hms(0, 0, 0));
+                calendar
+                    .next_after(midnight.with_timezone(&Utc) - Duration::seconds(1))
+                    .is_some_and(|meeting| {
+                        meeting.with_timezone(&self.timezone).date().naive_local() == date
+                    })
+                    && !overrides.replaces(date)
+            }
+            None => self.meeting_on(overrides, date).is_some(),
+        };
+        regular
+            || overrides
+                .extra_meetings(&self.timezone)
+                .any(|meeting| meeting.date().naive_local() == date)
+    }
+
+    fn read_calendar(&self) -> Option<Calendar> {
+        self.calendar
+            .as_ref()
+            .map(|path| ics::read_calendar(path, self.timezone))
+    }
+
+    // How many days to look for a regular meeting. Every cancelled meeting
+    // can push the next one another period forward.
+    fn search_days(&self, overrides: &Overrides) -> i64 {
+        7 * i64::from(self.every.max(1)) * (overrides.overrides.len() as i64 + 1)
+    }
+
+    /// The regular meeting on `date`, unless it has been cancelled or moved.
+    fn meeting_on(&self, overrides: &Overrides, date: NaiveDate) -> Option<DateTime<Tz>> {
+        if !self.weekdays.contains(&date.weekday())
+            || !self.in_meeting_week(date)
+            || overrides.replaces(date)
+        {
+            return None;
+        }
+        Some(resolve_local(&self.timezone, date.and_time(self.time)))
+    }
+
+    fn in_meeting_week(&self, date: NaiveDate) -> bool {
+        match self.start {
+            Some(start) if self.every > 1 => {
+                let monday = |date: NaiveDate| {
+                    date - Duration::days(date.weekday().num_days_from_monday().into())
+                };
+                let weeks = (monday(date) - monday(start)).num_weeks();
+                weeks.rem_euclid(self.every.into()) == 0
+            }
+            _ => true,
+        }
+    }
+}
+
+/// A one-off change to the regular schedule. Times are local to the timezone
+/// of the schedule.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Override {
+    Cancel(NaiveDate),
+    Move { from: NaiveDate, to: NaiveDateTime },
+    Extra(NaiveDateTime),
+}
+
+impl fmt::Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Override::Cancel(date) => write!(f, "The meeting on {} is cancelled", date),
+            Override::Move { from, to } => write!(
+                f,
+                "The meeting on {} is moved to {}",
+                from,
+                to.format(DATETIME_FORMAT)
+            ),
+            Override::Extra(at) => {
+                write!(f, "Extra meeting on {}", at.format(DATETIME_FORMAT))
+            }
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Overrides {
+    overrides: Vec<Override>,
+}
+
+impl Overrides {
+    fn write(&self) {
+        fs::write(
+            std::path::Path::new("overrides.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize overrides"),
+        )
+        .expect("Can't write overrides.json")
+    }
+
+    /// Cancel all meetings on `date`, including extra and moved meetings.
+    pub fn cancel(&mut self, date: NaiveDate) {
+        self.set_cancelled(date);
+        self.write();
+    }
+
+    fn set_cancelled(&mut self, date: NaiveDate) {
+        self.overrides
+            .retain(|o| !matches!(o, Override::Extra(at) if at.date() == date));
+        for o in &mut self.overrides {
+            if let Override::Move { from, to } = o {
+                // The meeting it was moved from stays replaced.
+                if to.date() == date {
+                    *o = Override::Cancel(*from);
+                }
+            }
+        }
+        self.overrides.push(Override::Cancel(date));
+    }
+
+    /// Move the meeting on `from` to `to`. An extra meeting is moved if there
+    /// is one on `from`.
+    pub fn reschedule(&mut self, from: NaiveDate, to: NaiveDateTime) {
+        match self.overrides.iter_mut().find(
+            |o| matches!(o, Override::Move { to, .. } | Override::Extra(to) if to.date() == from),
+        ) {
+            Some(Override::Move { to: old, .. }) | Some(Override::Extra(old)) => *old = to,
+            _ => self.overrides.push(Override::Move { from, to }),
+        }
+        self.write();
+    }
+
+    pub fn add_extra(&mut self, at: NaiveDateTime) {
+        self.overrides.push(Override::Extra(at));
+        self.write();
+    }
+
+    // Whether the regular meeting on `date` has been cancelled or moved.
+    fn replaces(&self, date: NaiveDate) -> bool {
+        self.overrides.iter().any(|o| match o {
+            Override::Cancel(cancelled) => *cancelled == date,
+            Override::Move { from, .. } => *from == date,
+            Override::Extra(_) => false,
+        })
+    }
+
+    fn extra_meetings<'a>(&'a self, timezone: &'a Tz) -> impl Iterator<Item = DateTime<Tz>> + 'a {
+        self.overrides.iter().filter_map(move |o| match o {
+            Override::Move { to, .. } | Override::Extra(to) => Some(resolve_local(timezone, *to)),
+            Override::Cancel(_) => None,
+        })
+    }
+}
+
+pub fn read_overrides() -> Overrides {
+    match fs::read_to_string("overrides.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing overrides.json"),
+        Err(_) => Overrides::default(),
+    }
+}
+
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").ok()
+}
+
+pub fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s.trim(), DATETIME_FORMAT).ok()
+}
+
+/// Convert a local time to a point in time, picking the first of two possible
+/// times when the clock is turned back and skipping ahead when the local time
+/// doesn't exist because the clock is turned forward.
+pub fn resolve_local(timezone: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(dt) => dt,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => resolve_local(timezone, local + Duration::hours(1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stockholm(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        chrono_tz::Europe::Stockholm
+            .ymd(y, m, d)
+            .and_hms(h, min, 0)
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn next_after() {
+        let schedule = Schedule::default();
+        // 2020-11-19 is a Thursday.
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 17, 9, 0))
+                .unwrap(),
+            stockholm(2020, 11, 19, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 19, 12, 0))
+                .unwrap(),
+            stockholm(2020, 11, 19, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 19, 12, 15))
+                .unwrap(),
+            stockholm(2020, 11, 26, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 19, 18, 0))
+                .unwrap(),
+            stockholm(2020, 11, 26, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 22, 23, 59))
+                .unwrap(),
+            stockholm(2020, 11, 26, 12, 15)
+        );
+    }
+
+    #[test]
+    fn previous_before() {
+        let schedule = Schedule::default();
+        assert_eq!(
+            schedule
+                .previous_before(&Overrides::default(), stockholm(2020, 11, 19, 12, 15))
+                .unwrap(),
+            stockholm(2020, 11, 19, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .previous_before(&Overrides::default(), stockholm(2020, 11, 19, 12, 0))
+                .unwrap(),
+            stockholm(2020, 11, 12, 12, 15)
+        );
+    }
+
+    #[test]
+    fn several_weekdays() {
+        let schedule = Schedule {
+            weekdays: vec![Weekday::Mon, Weekday::Thu],
+            ..Schedule::default()
+        };
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 19, 13, 0))
+                .unwrap(),
+            stockholm(2020, 11, 23, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 23, 13, 0))
+                .unwrap(),
+            stockholm(2020, 11, 26, 12, 15)
+        );
+    }
+
+    #[test]
+    fn every_other_week() {
+        let schedule = Schedule {
+            every: 2,
+            start: Some(NaiveDate::from_ymd(2020, 11, 16)),
+            ..Schedule::default()
+        };
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 19, 13, 0))
+                .unwrap(),
+            stockholm(2020, 12, 3, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2020, 11, 27, 13, 0))
+                .unwrap(),
+            stockholm(2020, 12, 3, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .previous_before(&Overrides::default(), stockholm(2020, 12, 2, 13, 0))
+                .unwrap(),
+            stockholm(2020, 11, 19, 12, 15)
+        );
+    }
+
+    #[test]
+    fn dst() {
+        let schedule = Schedule::default();
+        // Clocks are turned back on 2020-10-25. The meeting is still at 12:15
+        // local time, which is an hour later in UTC.
+        let before = schedule
+            .next_after(&Overrides::default(), stockholm(2020, 10, 20, 12, 0))
+            .unwrap();
+        let after = schedule
+            .next_after(&Overrides::default(), stockholm(2020, 10, 27, 12, 0))
+            .unwrap();
+        assert_eq!(before, stockholm(2020, 10, 22, 12, 15));
+        assert_eq!(after, stockholm(2020, 10, 29, 12, 15));
+        assert_eq!(after - before, Duration::weeks(1) + Duration::hours(1));
+
+        // 02:30 doesn't exist on 2021-03-28 and happens twice on 2021-10-31.
+        let schedule = Schedule {
+            weekdays: vec![Weekday::Sun],
+            time: NaiveTime::from_hms(2, 30, 0),
+            ..Schedule::default()
+        };
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2021, 3, 27, 12, 0))
+                .unwrap(),
+            Utc.ymd(2021, 3, 28).and_hms(1, 30, 0)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&Overrides::default(), stockholm(2021, 10, 30, 12, 0))
+                .unwrap(),
+            Utc.ymd(2021, 10, 31).and_hms(0, 30, 0)
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let schedule = Schedule::default();
+        let datetime = |s| parse_datetime(s).unwrap();
+        let overrides = Overrides {
+            overrides: vec![
+                Override::Cancel(NaiveDate::from_ymd(2020, 11, 19)),
+                Override::Cancel(NaiveDate::from_ymd(2020, 11, 26)),
+                Override::Move {
+                    from: NaiveDate::from_ymd(2020, 12, 3),
+                    to: datetime("2020-12-04 17:00"),
+                },
+                Override::Extra(datetime("2020-12-08 18:00")),
+            ],
+        };
+        assert_eq!(
+            schedule
+                .next_after(&overrides, stockholm(2020, 11, 18, 12, 0))
+                .unwrap(),
+            stockholm(2020, 12, 4, 17, 0)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&overrides, stockholm(2020, 12, 4, 17, 0))
+                .unwrap(),
+            stockholm(2020, 12, 8, 18, 0)
+        );
+        assert_eq!(
+            schedule
+                .next_after(&overrides, stockholm(2020, 12, 8, 18, 0))
+                .unwrap(),
+            stockholm(2020, 12, 10, 12, 15)
+        );
+        assert_eq!(
+            schedule
+                .previous_before(&overrides, stockholm(2020, 12, 3, 13, 0))
+                .unwrap(),
+            stockholm(2020, 11, 12, 12, 15)
+        );
+        assert!(schedule.has_meeting_on(&overrides, NaiveDate::from_ymd(2020, 12, 4)));
+        assert!(!schedule.has_meeting_on(&overrides, NaiveDate::from_ymd(2020, 12, 3)));
+    }
+
+    #[test]
+    fn cancel_moved() {
+        let schedule = Schedule::default();
+        let mut overrides = Overrides {
+            overrides: vec![Override::Move {
+                from: NaiveDate::from_ymd(2020, 12, 3),
+                to: parse_datetime("2020-12-04 17:00").unwrap(),
+            }],
+        };
+        overrides.set_cancelled(NaiveDate::from_ymd(2020, 12, 4));
+        assert!(!schedule.has_meeting_on(&overrides, NaiveDate::from_ymd(2020, 12, 4)));
+        assert!(!schedule.has_meeting_on(&overrides, NaiveDate::from_ymd(2020, 12, 3)));
+        assert_eq!(
+            schedule
+                .next_after(&overrides, stockholm(2020, 12, 2, 12, 0))
+                .unwrap(),
+            stockholm(2020, 12, 10, 12, 15)
+        );
+    }
+}
@@ -1,50 +1,254 @@
-use crate::agenda::{self, parse_message, AgendaPoint, Emoji};
-use crate::reminder::ReminderType;
+use crate::chat::{ChatAdapter, Connection, Incoming, Reaction};
+use crate::config;
+use crate::user::{Platform, User};
+use crate::vote::{self, Ballot};
 
-use futures::join;
 use slack::{error::Error, Event, Message};
-use slack_api::{reactions, users};
+use slack_api::{chat, files, reactions, usergroups_users, users};
 use std::{
     collections::{hash_map::Entry, HashMap},
-    sync::{Arc, Mutex},
-};
-use tokio::{
-    runtime::Runtime,
-    sync::{mpsc, watch},
-    task::{spawn, spawn_blocking},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
 };
+use tokio::{runtime::Runtime, task::spawn_blocking};
 use tokio_compat_02::FutureExt;
 
 const TOKEN: Option<&str> = None;
 const CHANNEL: Option<&str> = None;
 
+/// The names of the emojis used to vote for the options of a ballot.
+const OPTIONS: [&str; vote::MAX_OPTIONS] = [
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "keycap_ten",
+];
+
+// How long the members of the configured user group are kept before they are
+// listed again.
+const USERGROUP_REFRESH: Duration = Duration::from_secs(10 * 60);
+
+struct Adapter {
+    sender: slack::Sender,
+    channel: String,
+    slack_token: String,
+    display_names: Arc<Mutex<HashMap<String, String>>>,
+}
+
 struct Handler {
-    sender: mpsc::UnboundedSender<AgendaPoint>,
-    slack_sender: slack::Sender,
+    incoming: mpsc::Sender<Incoming>,
     slack_channel: Option<String>,
     print_channels: bool,
     slack_token: String,
     display_names: Arc<Mutex<HashMap<String, String>>>,
+    usergroup: Option<Usergroup>,
+}
+
+/// The members of the configured user group, who have the configured role.
+struct Usergroup {
+    listed: Instant,
+    role: Option<String>,
+    members: Vec<String>,
 }
 
 impl Handler {
     fn new(
-        sender: mpsc::UnboundedSender<AgendaPoint>,
-        slack_sender: slack::Sender,
+        incoming: mpsc::Sender<Incoming>,
         slack_channel: Option<String>,
         slack_token: String,
+        display_names: Arc<Mutex<HashMap<String, String>>>,
     ) -> Self {
         Self {
-            sender,
-            slack_sender,
+            incoming,
             slack_channel: slack_channel.clone(),
             print_channels: slack_channel.is_none(),
             slack_token,
-            display_names: Arc::new(Mutex::new(HashMap::new())),
+            display_names,
+            usergroup: None,
+        }
+    }
+
+    fn user(&self, id: String) -> User {
+        user(&self.display_names, &self.slack_token, id)
+    }
+
+    /// The configured role, if the user is in the configured user group.
+    fn roles(&mut self, id: &str) -> Vec<String> {
+        if !self
+            .usergroup
+            .as_ref()
+            .is_some_and(|usergroup| usergroup.listed.elapsed() < USERGROUP_REFRESH)
+        {
+            self.usergroup = Some(self.list_usergroup());
+        }
+        match &self.usergroup {
+            Some(Usergroup {
+                role: Some(role),
+                members,
+                ..
+            }) if members.iter().any(|user| user == id) => vec![role.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn list_usergroup(&self) -> Usergroup {
+        let permissions = config::read_config().permissions;
+        let (role, usergroup) = match (permissions.role(), &permissions.slack_usergroup) {
+            (Some(role), Some(usergroup)) => (role.to_string(), usergroup.clone()),
+            _ => {
+                return Usergroup {
+                    listed: Instant::now(),
+                    role: None,
+                    members: Vec::new(),
+                }
+            }
+        };
+        let client = slack_api::requests::default_client().unwrap();
+        let members = match Runtime::new().unwrap().block_on(
+            usergroups_users::list(
+                &client,
+                &self.slack_token,
+                &usergroups_users::ListRequest {
+                    usergroup: &usergroup,
+                    include_disabled: None,
+                },
+            )
+            .compat(),
+        ) {
+            Ok(response) => response.users.unwrap_or_default(),
+            Err(e) => {
+                println!("Can't list the members of {}: {}", usergroup, e);
+                Vec::new()
+            }
+        };
+        Usergroup {
+            listed: Instant::now(),
+            role: Some(role),
+            members,
+        }
+    }
+
+    fn on_reaction(
+        &self,
+        cli: &slack::RtmClient,
+        user: String,
+        reaction: String,
+        item: slack::Item,
+        add: bool,
+    ) {
+        let our_id = cli
+            .start_response()
+            .slf
+            .as_ref()
+            .and_then(|slf| slf.id.as_deref());
+        if our_id == Some(user.as_str()) {
+            return;
+        }
+        if let slack::Item::Message { channel, ts } = item {
+            if self.slack_channel.as_ref() == Some(&channel) {
+                let _ = self.incoming.send(Incoming::Reaction {
+                    message: ts.to_string(),
+                    user: self.user(user),
+                    reaction,
+                    add,
+                });
+            }
         }
     }
 }
 
+impl ChatAdapter for Adapter {
+    fn platform(&self) -> Platform {
+        Platform::Slack
+    }
+
+    fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    fn send_message(&self, text: &str) {
+        //TODO Sending messages is very slow sometimes. Have seen delays
+        // from 5 up to 20(!) seconds.
+        self.sender.send_typing(&self.channel).unwrap();
+        self.sender.send_message(&self.channel, text).unwrap();
+    }
+
+    fn send_file(&self, filename: &str, content: &str) {
+        Runtime::new()
+            .unwrap()
+            .block_on(upload_file(&self.slack_token, &self.channel, filename, content).compat());
+    }
+
+    fn post_ballot(&self, text: &str, ballot: Ballot) {
+        Runtime::new()
+            .unwrap()
+            .block_on(post_ballot(&self.slack_token, &self.channel, text, ballot).compat());
+    }
+
+    fn send_direct_message(&self, user: &User, text: &str) {
+        Runtime::new()
+            .unwrap()
+            .block_on(send_direct_message(&self.slack_token, &user.id, text).compat());
+    }
+
+    fn reaction_name(&self, reaction: Reaction) -> &'static str {
+        match reaction {
+            Reaction::Ok => "+1",
+            Reaction::Confirmation => "white_check_mark",
+            Reaction::Option(option) => OPTIONS[option],
+        }
+    }
+
+    fn react(&self, message: &str, reaction: &str) {
+        let ts = match message.parse() {
+            Ok(ts) => ts,
+            Err(_) => {
+                println!("Invalid Slack timestamp '{}'", message);
+                return;
+            }
+        };
+        let client = slack_api::requests::default_client().unwrap();
+        Runtime::new()
+            .unwrap()
+            .block_on(
+                reactions::add(
+                    &client,
+                    &self.slack_token,
+                    &reactions::AddRequest {
+                        name: reaction,
+                        file: None,
+                        file_comment: None,
+                        channel: Some(&self.channel),
+                        timestamp: Some(ts),
+                    },
+                )
+                .compat(),
+            )
+            .unwrap();
+    }
+
+    fn resolve_user(&self, id: &str) -> Option<User> {
+        Some(user(&self.display_names, &self.slack_token, id.to_string()))
+    }
+}
+
+fn user(
+    display_names: &Arc<Mutex<HashMap<String, String>>>,
+    slack_token: &str,
+    id: String,
+) -> User {
+    let name = Runtime::new().unwrap().block_on(
+        get_or_insert_display_name(Arc::clone(display_names), id.clone(), slack_token).compat(),
+    );
+    User::new(Platform::Slack, &id, &name)
+}
+
 async fn get_or_insert_display_name(
     display_names: Arc<Mutex<HashMap<String, String>>>,
     user_id: String,
@@ -73,6 +277,85 @@ async fn get_or_insert_display_name(
     }
 }
 
+async fn upload_file(slack_token: &str, channel: &str, filename: &str, content: &str) {
+    let client = slack_api::requests::default_client().unwrap();
+    files::upload(
+        &client,
+        slack_token,
+        &files::UploadRequest {
+            content: Some(content),
+            filename: Some(filename),
+            channels: Some(channel),
+            ..Default::default()
+        },
+    )
+    .compat()
+    .await
+    .unwrap();
+}
+
+async fn post_ballot(slack_token: &str, channel: &str, text: &str, ballot: Ballot) {
+    let client = slack_api::requests::default_client().unwrap();
+    // Posted through the web API since the RTM API doesn't tell the
+    // timestamp of the message, which is needed to react to it.
+    let ts = match chat::post_message(
+        &client,
+        slack_token,
+        &chat::PostMessageRequest {
+            channel,
+            text,
+            as_user: Some(true),
+            ..Default::default()
+        },
+    )
+    .compat()
+    .await
+    {
+        Ok(response) => response.ts.unwrap(),
+        Err(e) => {
+            println!("Can't post ballot: {}", e);
+            return;
+        }
+    };
+    for option in 0..ballot.options() {
+        reactions::add(
+            &client,
+            slack_token,
+            &reactions::AddRequest {
+                name: OPTIONS[option],
+                file: None,
+                file_comment: None,
+                channel: Some(channel),
+                timestamp: Some(ts.clone()),
+            },
+        )
+        .compat()
+        .await
+        .unwrap();
+    }
+    vote::read_ballots().add(Platform::Slack, channel, &ts.to_string(), ballot);
+}
+
+async fn send_direct_message(slack_token: &str, user: &str, text: &str) {
+    let client = slack_api::requests::default_client().unwrap();
+    // Messages posted to a user ID end up in the bot's direct messages.
+    if let Err(e) = chat::post_message(
+        &client,
+        slack_token,
+        &chat::PostMessageRequest {
+            channel: user,
+            text,
+            as_user: Some(true),
+            ..Default::default()
+        },
+    )
+    .compat()
+    .await
+    {
+        println!("Can't send a direct message to {}: {}", user, e);
+    }
+}
+
 impl slack::EventHandler for Handler {
     fn on_event(&mut self, cli: &slack::RtmClient, event: slack::Event) {
         match event {
@@ -96,60 +379,37 @@ impl slack::EventHandler for Handler {
                 }
             }
             Event::Message(msg) => {
-                if let Some(channel) = &self.slack_channel.clone() {
-                    match *msg {
-                        Message::Standard(msg) => {
-                            if msg.channel.is_some() && *channel == msg.channel.unwrap() {
-                                //TODO
-                                let user = match msg.user {
-                                    Some(s) => Runtime::new().unwrap().block_on(
-                                        get_or_insert_display_name(
-                                            Arc::clone(&self.display_names),
-                                            s,
-                                            &self.slack_token,
-                                        )
-                                        .compat(),
-                                    ),
-                                    None => "??".to_string(),
-                                };
-                                match parse_message(
-                                    &msg.text.unwrap_or("".to_string()),
-                                    &user,
-                                    |s: String| {
-                                        self.slack_sender
-                                            .send_message(channel.as_str(), &s)
-                                            .unwrap();
-                                    },
-                                    &self.sender,
-                                ) {
-                                    Some(Emoji::Ok) => {
-                                        let client = slack_api::requests::default_client().unwrap();
-                                        Runtime::new()
-                                            .unwrap()
-                                            .block_on(
-                                                reactions::add(
-                                                    &client,
-                                                    &self.slack_token,
-                                                    &reactions::AddRequest {
-                                                        name: "+1",
-                                                        file: None,
-                                                        file_comment: None,
-                                                        channel: Some(channel.as_str()),
-                                                        timestamp: Some(msg.ts.unwrap()),
-                                                    },
-                                                )
-                                                .compat(),
-                                            )
-                                            .unwrap();
-                                    }
-                                    _ => {} // parse_message return
-                                }
-                            }
-                        }
-                        _ => {} // message type
+                if let Message::Standard(msg) = *msg {
+                    if msg.channel.is_some() && self.slack_channel == msg.channel {
+                        //TODO
+                        let sender = match msg.user {
+                            Some(s) => User {
+                                roles: self.roles(&s),
+                                ..self.user(s)
+                            },
+                            None => User::new(Platform::Slack, "", "??"),
+                        };
+                        let _ = self.incoming.send(Incoming::Message {
+                            id: msg.ts.map(|ts| ts.to_string()).unwrap_or_default(),
+                            sender,
+                            text: msg.text.unwrap_or_default(),
+                            mentions: Vec::new(),
+                        });
                     }
                 }
             }
+            Event::ReactionAdded {
+                user,
+                reaction,
+                item,
+                ..
+            } => self.on_reaction(cli, user, reaction, item, true),
+            Event::ReactionRemoved {
+                user,
+                reaction,
+                item,
+                ..
+            } => self.on_reaction(cli, user, reaction, item, false),
             _ => {} // event type
         }
     }
@@ -159,95 +419,50 @@ impl slack::EventHandler for Handler {
     fn on_connect(&mut self, _cli: &slack::RtmClient) {}
 }
 
-pub async fn handle(
-    sender: mpsc::UnboundedSender<AgendaPoint>,
-    receiver: mpsc::UnboundedReceiver<AgendaPoint>,
-    reminder: watch::Receiver<ReminderType>,
-) {
+/// Connect to Slack. Events are received even if no channel is configured,
+/// so that the channels can be listed, but nothing is posted.
+pub async fn connect() -> Option<Connection> {
     println!("Setting up Slack");
 
     let token = std::env::var("SLACK_API_TOKEN")
         .unwrap_or_else(|_| TOKEN.expect("Missing slack token").to_string());
     let channel = match std::env::var("SLACK_CHANNEL") {
         Ok(channel) => Some(channel),
-        Err(_) => match CHANNEL {
-            Some(channel) => Some(channel.to_string()),
-            None => None,
-        },
+        Err(_) => CHANNEL.map(str::to_string),
     };
     let slack_token = token.to_string();
     let client = spawn_blocking(move || slack::RtmClient::login(&token).unwrap())
         .await
         .unwrap();
 
+    let display_names = Arc::new(Mutex::new(HashMap::new()));
+    let (sender, incoming) = mpsc::channel();
     let mut handler = Handler::new(
         sender,
-        client.sender().clone(),
         channel.clone(),
-        slack_token,
+        slack_token.clone(),
+        Arc::clone(&display_names),
     );
     let slack_sender = client.sender().clone();
 
-    let (_, _, _) = join!(
-        spawn(receive_from_discord(
-            receiver,
-            slack_sender.clone(),
-            channel.clone()
-        )),
-        spawn(handle_reminders(reminder, slack_sender, channel)),
-        spawn_blocking(move || {
-            loop {
-                match client.run(&mut handler) {
-                    Ok(_) => {}
-                    Err(Error::WebSocket(_)) => println!("Restart slack socket"),
-                    Err(e) => {
-                        println!("Error: {}", e);
-                        break;
-                    }
-                }
+    spawn_blocking(move || loop {
+        match client.run(&mut handler) {
+            Ok(_) => {}
+            Err(Error::WebSocket(_)) => println!("Restart slack socket"),
+            Err(e) => {
+                println!("Error: {}", e);
+                break;
             }
-        }),
-    );
-}
-
-async fn receive_from_discord(
-    mut receiver: mpsc::UnboundedReceiver<AgendaPoint>,
-    sender: slack::Sender,
-    channel: Option<String>,
-) {
-    if let Some(channel) = channel {
-        while let Some(point) = receiver.recv().await {
-            //TODO Sending messages is very slow sometimes. Have seen delays
-            // from 5 up to 20(!) seconds.
-            sender.send_typing(&channel).unwrap();
-            sender
-                .send_message(&channel, &point.to_add_message())
-                .unwrap();
-            println!("Slack message sent");
         }
-    }
-}
+    });
 
-async fn handle_reminders(
-    mut reminder: watch::Receiver<ReminderType>,
-    sender: slack::Sender,
-    channel: Option<String>,
-) {
-    if let Some(channel) = channel {
-        while reminder.changed().await.is_ok() {
-            let reminder = reminder.borrow();
-            match *reminder {
-                ReminderType::OneHour => {
-                    sender.send_typing(&channel).unwrap();
-                    sender
-                        .send_message(
-                            &channel,
-                            &format!("Meeting in one hour!\n{}", agenda::read_agenda()),
-                        )
-                        .unwrap();
-                }
-                ReminderType::Void => {}
-            }
-        }
-    }
+    Some(Connection {
+        adapter: Arc::new(Adapter {
+            sender: slack_sender,
+            channel: channel?,
+            slack_token,
+            display_names,
+        }),
+        incoming,
+    })
 }
@@ -0,0 +1,213 @@
+use crate::user::User;
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs};
+
+/// An action item from a meeting.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Todo {
+    id: u32,
+    text: String,
+    assignee: String,
+    // Set if the assignee was mentioned when the action item was added.
+    #[serde(default)]
+    assignee_user: Option<User>,
+    added_by: String,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+    #[serde(default)]
+    done: bool,
+}
+
+impl fmt::Display for Todo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} ({}", self.id, self.text, self.assignee)?;
+        if let Some(due) = self.due {
+            write!(f, ", due {}", due)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Todo {
+    pub fn to_add_message(&self) -> String {
+        format!("Action item {} added by {}", self, self.added_by)
+    }
+
+    pub fn to_done_message(&self) -> String {
+        format!("Action item #{} '{}' done", self.id, self.text)
+    }
+
+    pub fn assignee_user(&self) -> Option<&User> {
+        self.assignee_user.as_ref()
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Todos {
+    todos: Vec<Todo>,
+    #[serde(default)]
+    last_id: u32,
+}
+
+impl Todos {
+    fn write(&self) {
+        fs::write(
+            std::path::Path::new("todos.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize todos"),
+        )
+        .expect("Can't write todos.json");
+    }
+
+    pub fn add(
+        &mut self,
+        text: &str,
+        assignee: &str,
+        assignee_user: Option<User>,
+        added_by: &str,
+        due: Option<NaiveDate>,
+    ) -> Todo {
+        self.last_id += 1;
+        let todo = Todo {
+            id: self.last_id,
+            text: text.to_string(),
+            assignee: assignee.to_string(),
+            assignee_user,
+            added_by: added_by.to_string(),
+            due,
+            done: false,
+        };
+        self.todos.push(todo.clone());
+        self.write();
+        todo
+    }
+
+    pub fn mark_done(&mut self, id: u32) -> Option<Todo> {
+        let todo = self
+            .todos
+            .iter_mut()
+            .find(|todo| todo.id == id && !todo.done)?;
+        todo.done = true;
+        let todo = todo.clone();
+        self.write();
+        Some(todo)
+    }
+
+    pub fn open(&self) -> impl Iterator<Item = &Todo> {
+        self.todos.iter().filter(|todo| !todo.done)
+    }
+
+    /// Open action items that are overdue or due within `days` days of
+    /// `today`, by assignee.
+    pub fn due_soon(&self, today: NaiveDate, days: i64) -> BTreeMap<&str, Vec<&Todo>> {
+        let mut due_soon = BTreeMap::new();
+        for todo in self.open() {
+            if todo
+                .due
+                .is_some_and(|due| due <= today + Duration::days(days))
+            {
+                due_soon
+                    .entry(todo.assignee.as_str())
+                    .or_insert_with(Vec::new)
+                    .push(todo);
+            }
+        }
+        due_soon
+    }
+}
+
+/// A reminder to `assignee` about their action items.
+pub fn to_nag_message(assignee: &str, todos: &[&Todo], today: NaiveDate) -> String {
+    let mut s = format!("Action items for {}:", assignee);
+    for todo in todos {
+        s.push_str(&format!("\n{}", todo));
+        if todo.due.is_some_and(|due| due < today) {
+            s.push_str(" [overdue]");
+        }
+    }
+    s
+}
+
+impl fmt::Display for Todos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = self
+            .open()
+            .map(|todo| todo.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(
+            f,
+            "{}",
+            match s.as_str() {
+                "" => "No open action items",
+                _ => &s,
+            }
+        )
+    }
+}
+
+/// Split "<text> by <YYYY-MM-DD>" into the text and the due date.
+pub fn parse_due(s: &str) -> (&str, Option<NaiveDate>) {
+    match s.rsplit_once(" by ") {
+        Some((text, due)) => match NaiveDate::parse_from_str(due.trim(), "%Y-%m-%d") {
+            Ok(due) => (text.trim(), Some(due)),
+            Err(_) => (s.trim(), None),
+        },
+        None => (s.trim(), None),
+    }
+}
+
+pub fn read_todos() -> Todos {
+    match fs::read_to_string("todos.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing todos.json"),
+        Err(_) => Todos::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_due() {
+        assert_eq!(
+            super::parse_due("Book the room by 2020-11-26"),
+            ("Book the room", Some(NaiveDate::from_ymd(2020, 11, 26)))
+        );
+        assert_eq!(
+            super::parse_due("Stand by the door"),
+            ("Stand by the door", None)
+        );
+    }
+
+    #[test]
+    fn due_soon() {
+        let today = NaiveDate::from_ymd(2020, 11, 23);
+        let mut todos = Todos::default();
+        let mut add = |text: &str, assignee: &str, due| {
+            todos.last_id += 1;
+            todos.todos.push(Todo {
+                id: todos.last_id,
+                text: text.to_string(),
+                assignee: assignee.to_string(),
+                assignee_user: None,
+                added_by: "Gustav".to_string(),
+                due,
+                done: false,
+            });
+        };
+        add("Overdue", "Alice", Some(today - Duration::days(3)));
+        add("This week", "Bob", Some(today + Duration::days(4)));
+        add("Next month", "Bob", Some(today + Duration::days(30)));
+        add("Whenever", "Alice", None);
+
+        let due_soon = todos.due_soon(today, 7);
+        assert_eq!(due_soon.keys().collect::<Vec<_>>(), vec![&"Alice", &"Bob"]);
+        assert_eq!(
+            to_nag_message("Alice", &due_soon["Alice"], today),
+            "Action items for Alice:\n#1 Overdue (Alice, due 2020-11-20) [overdue]"
+        );
+        assert_eq!(due_soon["Bob"].len(), 1);
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Platform {
+    // The lowercase names are the keys of a board member's IDs in config.json.
+    #[serde(alias = "discord")]
+    Discord,
+    #[serde(alias = "slack")]
+    Slack,
+}
+
+/// A user on one of the platforms.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct User {
+    pub platform: Platform,
+    pub id: String,
+    pub name: String,
+    // The roles the user has on the platform. Only known for the sender of a
+    // message.
+    #[serde(skip)]
+    pub roles: Vec<String>,
+}
+
+impl User {
+    pub fn new(platform: Platform, id: &str, name: &str) -> Self {
+        Self {
+            platform,
+            id: id.to_string(),
+            name: name.to_string(),
+            roles: Vec::new(),
+        }
+    }
+}
+
+/// Split a mention ("<@ID>", "<@!ID>" or "<@ID|name>") at the start of `s`
+/// into the mentioned ID and the rest of `s`.
+pub fn parse_mention(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start().strip_prefix("<@")?;
+    let (mention, rest) = s.split_once('>')?;
+    let id = mention.trim_start_matches('!');
+    let id = id.split('|').next()?;
+    Some((id, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_mention() {
+        assert_eq!(
+            super::parse_mention("<@U123> rest"),
+            Some(("U123", " rest"))
+        );
+        assert_eq!(super::parse_mention("<@U123|gustav>"), Some(("U123", "")));
+        assert_eq!(super::parse_mention("<@!4567> x"), Some(("4567", " x")));
+        assert_eq!(super::parse_mention("@gustav x"), None);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    agenda, poll,
+    user::{Platform, User},
+};
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// How many options a ballot can have. Every platform has a reaction for each
+/// of them.
+pub const MAX_OPTIONS: usize = 10;
+
+// Only the latest ballots are kept.
+const MAX_BALLOTS: usize = 50;
+
+/// What the options of a posted message are votes for.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Ballot {
+    // The IDs of the agenda points, in the order they were posted.
+    Agenda(Vec<u32>),
+    Poll { id: u32, options: usize },
+}
+
+impl Ballot {
+    pub fn options(&self) -> usize {
+        match self {
+            Ballot::Agenda(points) => points.len(),
+            Ballot::Poll { options, .. } => *options,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct PostedBallot {
+    platform: Platform,
+    channel: String,
+    message: String,
+    ballot: Ballot,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Ballots {
+    ballots: Vec<PostedBallot>,
+}
+
+impl Ballots {
+    fn write(&self) {
+        fs::write(
+            std::path::Path::new("ballots.json"),
+            serde_json::to_string_pretty(&self).expect("Can't serialize ballots"),
+        )
+        .expect("Can't write ballots.json");
+    }
+
+    /// Remember that `ballot` was posted as `message` in `channel`.
+    pub fn add(&mut self, platform: Platform, channel: &str, message: &str, ballot: Ballot) {
+        self.ballots.push(PostedBallot {
+            platform,
+            channel: channel.to_string(),
+            message: message.to_string(),
+            ballot,
+        });
+        if self.ballots.len() > MAX_BALLOTS {
+            self.ballots.remove(0);
+        }
+        self.write();
+    }
+
+    fn find(&self, platform: Platform, channel: &str, message: &str) -> Option<&Ballot> {
+        self.ballots
+            .iter()
+            .find(|posted| {
+                posted.platform == platform
+                    && posted.channel == channel
+                    && posted.message == message
+            })
+            .map(|posted| &posted.ballot)
+    }
+}
+
+/// Add or remove a vote for `option` given by `user` reacting to `message` in
+/// `channel`. Reactions to other messages are ignored.
+pub fn react(channel: &str, message: &str, option: usize, user: User, add: bool) {
+    let ballots = read_ballots();
+    let ballot = match ballots.find(user.platform, channel, message) {
+        Some(ballot) => ballot,
+        None => return,
+    };
+    if option >= ballot.options() {
+        return;
+    }
+    match ballot {
+        Ballot::Agenda(points) => agenda::read_agenda().vote(points[option], user, add),
+        Ballot::Poll { id, .. } => poll::read_polls().vote(*id, option, user, add),
+    }
+}
+
+pub fn read_ballots() -> Ballots {
+    match fs::read_to_string("ballots.json") {
+        Ok(s) => serde_json::from_str(&s).expect("Error parsing ballots.json"),
+        Err(_) => Ballots::default(),
+    }
+}